@@ -0,0 +1,740 @@
+use clap::builder::BoolishValueParser;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use lettre::message::Mailbox;
+use reqwest::Url;
+use std::path::PathBuf;
+
+/// Sends failed tests from JUnit XML reports to Slack, other chat services or email.
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    send: SendArgs,
+}
+
+impl Cli {
+    /// Returns the selected subcommand, falling back to `send` when none is given.
+    ///
+    /// A Slack destination is only required for `send`, so it is validated here
+    /// rather than by clap, which would also demand it for the other subcommands.
+    pub fn into_command(self) -> Result<Command, clap::Error> {
+        let command = self.command.unwrap_or(Command::Send(Box::new(self.send)));
+        let message = match &command {
+            Command::Send(args) => Some(&args.message),
+            Command::Summarize(args) => Some(&args.message),
+            Command::Diff(args) => Some(&args.message),
+            Command::Validate(_) => None,
+        };
+        if let Some(message) = message {
+            check_layout(message)?;
+        }
+        if let Command::Send(args) = &command {
+            if args.notifiers.is_empty() {
+                return Err(Cli::command().error(
+                    ErrorKind::MissingRequiredArgument,
+                    "a destination is required:\n  --webhook-url, --bot-token, --teams-webhook-url, --discord-webhook-url,\n  --mattermost-webhook-url, --rocketchat-webhook-url, --google-chat-webhook-url\n  or --smtp-host",
+                ));
+            }
+            check_threads(&args.message, &args.notifiers)?;
+            check_smtp_auth(&args.notifiers.email)?;
+            if args.notify_on_recovery && args.history.history_file.is_none() {
+                return Err(Cli::command().error(
+                    ErrorKind::MissingRequiredArgument,
+                    "recovery is detected from the previous run:\n  --history-file <HISTORY_FILE> is required with --notify-on-recovery",
+                ));
+            }
+        }
+        if let Command::Diff(args) = &command {
+            check_threads(&args.message, &args.notifiers)?;
+            check_smtp_auth(&args.notifiers.email)?;
+        }
+        Ok(command)
+    }
+}
+
+/// Clusters always fit in one message and are not grouped, so the options
+/// that split or group the list of failures do not apply to them.
+fn check_layout(message: &MessageArgs) -> Result<(), clap::Error> {
+    if message.layout == Layout::Clusters
+        && (message.uses_thread()
+            || message.overflow != Overflow::Truncate
+            || message.group_by != GroupBy::None)
+    {
+        return Err(Cli::command().error(
+            ErrorKind::ArgumentConflict,
+            "--layout clusters cannot be combined with --threaded, --overflow or --group-by",
+        ));
+    }
+    Ok(())
+}
+
+/// Credentials must not be sent over an unencrypted connection.
+fn check_smtp_auth(email: &EmailArgs) -> Result<(), clap::Error> {
+    if email.smtp_tls == SmtpTls::None && email.smtp_username.is_some() {
+        return Err(Cli::command().error(
+            ErrorKind::ArgumentConflict,
+            "--smtp-username and --smtp-password would be sent in the clear:\n  use --smtp-tls starttls or implicit to authenticate",
+        ));
+    }
+    Ok(())
+}
+
+/// Thread replies are only posted through the Web API.
+fn check_threads(message: &MessageArgs, notifiers: &NotifierArgs) -> Result<(), clap::Error> {
+    if message.uses_thread() && notifiers.slack.bot_token.is_none() {
+        return Err(Cli::command().error(
+            ErrorKind::MissingRequiredArgument,
+            "threaded replies need the Web API:\n  --bot-token <BOT_TOKEN> is required with --threaded or --overflow thread",
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Post failed tests to the configured services (the default)
+    Send(Box<SendArgs>),
+    /// Print the Slack message without sending it
+    Summarize(Box<SummarizeArgs>),
+    /// Check that the reports can be found and parsed
+    Validate(ReportArgs),
+    /// Compare the reports with a baseline and post the regressions, or only
+    /// print them when no destination is given
+    Diff(Box<DiffArgs>),
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    /// JUnit XML files, directories or glob patterns
+    #[arg(
+        env = "JUNIT_REPORTS",
+        value_delimiter = ',',
+        default_value = "junit.xml"
+    )]
+    pub inputs: Vec<String>,
+}
+
+#[derive(Args, Clone)]
+pub struct MessageArgs {
+    /// Title shown at the top of the message
+    #[arg(
+        long,
+        env = "SLACK_MESSAGE_TITLE",
+        default_value = "Test Results",
+        value_parser = non_empty
+    )]
+    pub title: String,
+
+    /// Number of stack trace lines shown for each failure (0 to hide them)
+    #[arg(long, env = "STACK_TRACE_LINES", default_value_t = 5)]
+    pub stack_trace_lines: usize,
+
+    /// Number of failures listed in the main message
+    #[arg(long, env = "MAX_FAILURES", default_value_t = 20)]
+    pub max_failures: usize,
+
+    /// What to do with failures beyond `--max-failures`
+    #[arg(long, env = "JUNIT_NOTIFY_OVERFLOW", value_enum, default_value_t = Overflow::Truncate)]
+    pub overflow: Overflow,
+
+    /// Post a compact summary and put the failure details in a thread
+    /// (requires --bot-token)
+    #[arg(long, env = "SLACK_THREADED", value_parser = BoolishValueParser::new())]
+    pub threaded: bool,
+
+    /// How failures are laid out in the message
+    #[arg(long, env = "JUNIT_NOTIFY_LAYOUT", value_enum, default_value_t = Layout::List)]
+    pub layout: Layout,
+
+    /// Group the listed failures under a heading per group
+    #[arg(long, env = "JUNIT_NOTIFY_GROUP_BY", value_enum, default_value_t = GroupBy::None)]
+    pub group_by: GroupBy,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    /// List failures in report order
+    None,
+    /// The top-level test suite
+    Suite,
+    /// The full path of nested test suites
+    SuitePath,
+    /// The Java package, i.e. the classname without its last segment
+    Package,
+    /// The `classname` attribute
+    Classname,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Layout {
+    /// Every failure with its details
+    List,
+    /// Failures with the same normalized error signature shown once, with a
+    /// count and a sample of test names (not with --threaded, --overflow or
+    /// --group-by)
+    Clusters,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Overflow {
+    /// Only mention how many more failures there are
+    Truncate,
+    /// Post the remaining failures in follow-up messages
+    FollowUp,
+    /// Post the remaining failures as replies in a thread (requires --bot-token)
+    Thread,
+}
+
+impl MessageArgs {
+    /// Whether some messages are posted as thread replies.
+    pub fn uses_thread(&self) -> bool {
+        self.threaded || self.overflow == Overflow::Thread
+    }
+}
+
+#[derive(Args)]
+pub struct SendArgs {
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    #[command(flatten)]
+    pub message: MessageArgs,
+
+    #[command(flatten)]
+    pub triage: TriageArgs,
+
+    #[command(flatten)]
+    pub notifiers: NotifierArgs,
+
+    /// Also post a summary when every test passed
+    #[arg(long, env = "NOTIFY_ON_SUCCESS", value_parser = BoolishValueParser::new())]
+    pub notify_on_success: bool,
+
+    /// Also post when tests only passed after being rerun
+    #[arg(long, env = "NOTIFY_ON_FLAKY", value_parser = BoolishValueParser::new())]
+    pub notify_on_flaky: bool,
+
+    /// Post a "back to green" message when the previous run failed and this
+    /// one passed (requires --history-file)
+    #[arg(long, env = "NOTIFY_ON_RECOVERY", value_parser = BoolishValueParser::new())]
+    pub notify_on_recovery: bool,
+
+    #[command(flatten)]
+    pub history: HistoryArgs,
+
+    #[command(flatten)]
+    pub routing: RoutingArgs,
+
+    #[command(flatten)]
+    pub mentions: MentionArgs,
+
+    #[command(flatten)]
+    pub delivery: DeliveryArgs,
+}
+
+#[derive(Args)]
+pub struct RoutingArgs {
+    /// CODEOWNERS file used to send each failure to the team that owns it
+    #[arg(long, env = "CODEOWNERS_FILE", requires = "owners_file")]
+    pub codeowners_file: Option<PathBuf>,
+
+    /// TOML file mapping CODEOWNERS owners to Slack webhooks or channels
+    #[arg(long, env = "OWNERS_FILE", requires = "codeowners_file")]
+    pub owners_file: Option<PathBuf>,
+
+    /// Also post every failure to the default destination when routing by
+    /// owner, not only those without an owner
+    #[arg(long, env = "OWNERSHIP_OVERVIEW", value_parser = BoolishValueParser::new())]
+    pub overview: bool,
+}
+
+#[derive(Args)]
+pub struct MentionArgs {
+    /// TOML file of rules choosing who is mentioned when tests fail
+    #[arg(long, env = "MENTIONS_FILE")]
+    pub mentions_file: Option<PathBuf>,
+
+    /// TOML schedule of on-call shifts, for rules that mention the on-call user
+    #[arg(long, env = "ON_CALL_FILE", requires = "mentions_file")]
+    pub on_call_file: Option<PathBuf>,
+}
+
+/// Every service that results can be posted to; each one that is configured
+/// gets the results.
+#[derive(Args)]
+pub struct NotifierArgs {
+    #[command(flatten)]
+    pub slack: SlackArgs,
+
+    #[command(flatten)]
+    pub teams: TeamsArgs,
+
+    #[command(flatten)]
+    pub discord: DiscordArgs,
+
+    #[command(flatten)]
+    pub markdown: MarkdownChatArgs,
+
+    #[command(flatten)]
+    pub google_chat: GoogleChatArgs,
+
+    #[command(flatten)]
+    pub email: EmailArgs,
+}
+
+impl NotifierArgs {
+    /// Whether no service is configured.
+    pub fn is_empty(&self) -> bool {
+        self.slack.webhook_url.is_none()
+            && self.slack.bot_token.is_none()
+            && self.teams.teams_webhook_url.is_none()
+            && self.discord.discord_webhook_url.is_none()
+            && self.markdown.mattermost_webhook_url.is_none()
+            && self.markdown.rocketchat_webhook_url.is_none()
+            && self.google_chat.google_chat_webhook_url.is_none()
+            && self.email.smtp_host.is_none()
+    }
+}
+
+#[derive(Args)]
+pub struct SlackArgs {
+    /// Slack incoming webhook URL
+    #[arg(
+        long,
+        env = "SLACK_WEBHOOK_URL",
+        hide_env_values = true,
+        conflicts_with = "bot_token"
+    )]
+    pub webhook_url: Option<Url>,
+
+    /// Slack bot token, to post with chat.postMessage instead of a webhook
+    #[arg(
+        long,
+        env = "SLACK_BOT_TOKEN",
+        hide_env_values = true,
+        requires = "channel"
+    )]
+    pub bot_token: Option<String>,
+
+    /// Channel name or ID to post to with --bot-token
+    #[arg(long, env = "SLACK_CHANNEL")]
+    pub channel: Option<String>,
+
+    /// Base URL of the Slack Web API
+    #[arg(long, env = "SLACK_API_URL", default_value = "https://slack.com/api")]
+    pub slack_api_url: Url,
+}
+
+#[derive(Args)]
+pub struct TeamsArgs {
+    /// Microsoft Teams incoming webhook or Workflows URL
+    #[arg(long, env = "TEAMS_WEBHOOK_URL", hide_env_values = true)]
+    pub teams_webhook_url: Option<Url>,
+}
+
+#[derive(Args)]
+pub struct DiscordArgs {
+    /// Discord webhook URL
+    #[arg(long, env = "DISCORD_WEBHOOK_URL", hide_env_values = true)]
+    pub discord_webhook_url: Option<Url>,
+}
+
+/// Chat services with Slack-like webhooks that render standard Markdown.
+#[derive(Args)]
+pub struct MarkdownChatArgs {
+    /// Mattermost incoming webhook URL
+    #[arg(long, env = "MATTERMOST_WEBHOOK_URL", hide_env_values = true)]
+    pub mattermost_webhook_url: Option<Url>,
+
+    /// Rocket.Chat incoming webhook URL
+    #[arg(long, env = "ROCKETCHAT_WEBHOOK_URL", hide_env_values = true)]
+    pub rocketchat_webhook_url: Option<Url>,
+
+    #[command(flatten)]
+    pub overrides: ChatOverrideArgs,
+}
+
+/// Overrides of the webhook's defaults for Mattermost and Rocket.Chat, where
+/// the webhook allows them.
+#[derive(Args)]
+pub struct ChatOverrideArgs {
+    /// Channel to post to instead of the webhook's default channel
+    #[arg(long, env = "CHAT_CHANNEL")]
+    pub chat_channel: Option<String>,
+
+    /// Name to post as
+    #[arg(long, env = "CHAT_USERNAME")]
+    pub chat_username: Option<String>,
+
+    /// URL of the picture to post with
+    #[arg(long, env = "CHAT_ICON_URL", conflicts_with = "chat_icon_emoji")]
+    pub chat_icon_url: Option<Url>,
+
+    /// Emoji to post with instead of a picture, e.g. `:test_tube:`
+    #[arg(long, env = "CHAT_ICON_EMOJI")]
+    pub chat_icon_emoji: Option<String>,
+}
+
+#[derive(Args)]
+pub struct GoogleChatArgs {
+    /// Google Chat incoming webhook URL
+    #[arg(long, env = "GOOGLE_CHAT_WEBHOOK_URL", hide_env_values = true)]
+    pub google_chat_webhook_url: Option<Url>,
+
+    /// Posts every run with the same key in one thread, e.g. the pipeline name
+    #[arg(
+        long,
+        env = "GOOGLE_CHAT_THREAD_KEY",
+        requires = "google_chat_webhook_url",
+        value_parser = non_empty
+    )]
+    pub google_chat_thread_key: Option<String>,
+}
+
+#[derive(Args)]
+pub struct EmailArgs {
+    /// SMTP server to send the results by email through
+    #[arg(long, env = "SMTP_HOST", requires_all = ["email_from", "email_to"])]
+    pub smtp_host: Option<String>,
+
+    /// SMTP port, by default 587 with STARTTLS, 465 with implicit TLS and 25
+    /// without TLS
+    #[arg(long, env = "SMTP_PORT")]
+    pub smtp_port: Option<u16>,
+
+    /// How the connection to the SMTP server is encrypted
+    #[arg(long, env = "SMTP_TLS", value_enum, default_value_t = SmtpTls::Starttls)]
+    pub smtp_tls: SmtpTls,
+
+    /// User name to authenticate with (not with --smtp-tls none)
+    #[arg(long, env = "SMTP_USERNAME", requires = "smtp_password")]
+    pub smtp_username: Option<String>,
+
+    /// Password to authenticate with
+    #[arg(
+        long,
+        env = "SMTP_PASSWORD",
+        hide_env_values = true,
+        requires = "smtp_username"
+    )]
+    pub smtp_password: Option<String>,
+
+    /// Sender address, e.g. `CI <ci@example.com>`
+    #[arg(long, env = "EMAIL_FROM", requires = "smtp_host")]
+    pub email_from: Option<Mailbox>,
+
+    /// Recipient addresses
+    #[arg(long, env = "EMAIL_TO", value_delimiter = ',', requires = "smtp_host")]
+    pub email_to: Vec<Mailbox>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS, failing if the server
+    /// cannot
+    Starttls,
+    /// Connect over TLS from the start (SMTPS)
+    Implicit,
+    /// Send in the clear, only for a local relay
+    None,
+}
+
+#[derive(Args)]
+pub struct DeliveryArgs {
+    /// How many times to retry a failed delivery
+    #[arg(long, env = "NOTIFY_RETRIES", default_value_t = 3)]
+    pub retries: u32,
+
+    /// Delay before the first retry in milliseconds, doubled on every attempt
+    #[arg(long, env = "NOTIFY_RETRY_DELAY_MS", default_value_t = 1000)]
+    pub retry_delay_ms: u64,
+
+    /// Give up retrying once this many seconds have passed
+    #[arg(long, env = "NOTIFY_DEADLINE_SECS", default_value_t = 60)]
+    pub deadline_secs: u64,
+
+    /// Timeout in seconds for each HTTP request as a whole, and for each step
+    /// of an SMTP session (connecting, each command and its reply)
+    #[arg(long, env = "NOTIFY_TIMEOUT_SECS", default_value_t = 10)]
+    pub timeout_secs: u64,
+}
+
+#[derive(Args)]
+pub struct SummarizeArgs {
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    #[command(flatten)]
+    pub message: MessageArgs,
+
+    #[command(flatten)]
+    pub triage: TriageArgs,
+
+    #[command(flatten)]
+    pub history: HistoryArgs,
+
+    #[command(flatten)]
+    pub mentions: MentionArgs,
+
+    /// How to print the message
+    #[arg(long, env = "JUNIT_NOTIFY_OUTPUT_FORMAT", value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// Baseline reports to compare against: files, directories or glob patterns
+    #[arg(long, env = "JUNIT_BASELINE", value_delimiter = ',', required = true)]
+    pub baseline: Vec<String>,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    #[command(flatten)]
+    pub message: MessageArgs,
+
+    #[command(flatten)]
+    pub triage: TriageArgs,
+
+    #[command(flatten)]
+    pub notifiers: NotifierArgs,
+
+    #[command(flatten)]
+    pub delivery: DeliveryArgs,
+}
+
+/// Files that change how individual failures are reported.
+#[derive(Args, Default)]
+pub struct TriageArgs {
+    /// TOML file of known-broken tests whose failures do not alert
+    #[arg(long, env = "QUARANTINE_FILE")]
+    pub quarantine_file: Option<PathBuf>,
+
+    /// TOML file of regex rules attributing failures to known issues
+    #[arg(long, env = "KNOWN_ISSUES_FILE")]
+    pub known_issues_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    /// JSON-lines file recording the failures of previous runs
+    #[arg(long, env = "HISTORY_FILE")]
+    pub history_file: Option<PathBuf>,
+
+    /// Project name that runs are recorded under in the history file
+    #[arg(long, env = "HISTORY_PROJECT", default_value = "default")]
+    pub project: String,
+
+    /// Branch name that runs are recorded under in the history file
+    #[arg(long, env = "HISTORY_BRANCH", default_value = "default")]
+    pub branch: String,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    /// The message text as it would appear in Slack
+    Text,
+    /// The JSON payload that would be posted to the webhook
+    Json,
+}
+
+fn non_empty(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() {
+        Err("must not be empty".to_string())
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_to_send_without_subcommand() {
+        let cli = Cli::try_parse_from([
+            "junit_to_slack_notification",
+            "--webhook-url",
+            "https://hooks.slack.com/services/T/B/X",
+            "a.xml",
+            "reports/",
+        ])
+        .unwrap();
+        match cli.into_command().unwrap() {
+            Command::Send(args) => assert_eq!(args.report.inputs, vec!["a.xml", "reports/"]),
+            _ => panic!("expected send"),
+        }
+    }
+
+    #[test]
+    fn test_subcommands_do_not_require_webhook() {
+        let cli = Cli::try_parse_from(["junit_to_slack_notification", "validate"]).unwrap();
+        match cli.into_command().unwrap() {
+            Command::Validate(args) => assert_eq!(args.inputs, vec!["junit.xml"]),
+            _ => panic!("expected validate"),
+        }
+    }
+
+    #[test]
+    fn test_send_requires_webhook() {
+        let cli = Cli::try_parse_from(["junit_to_slack_notification", "send"]).unwrap();
+        let err = cli.into_command().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_send_accepts_teams_webhook_alone() {
+        let cli = Cli::try_parse_from([
+            "junit_to_slack_notification",
+            "--teams-webhook-url",
+            "https://example.webhook.office.com/webhookb2/X",
+        ])
+        .unwrap();
+        let Ok(Command::Send(args)) = cli.into_command() else {
+            panic!("expected the send command");
+        };
+        assert!(!args.notifiers.is_empty());
+        assert!(args.notifiers.slack.webhook_url.is_none());
+    }
+
+    #[test]
+    fn test_bot_token_requires_channel() {
+        let args = [
+            "junit_to_slack_notification",
+            "send",
+            "--bot-token",
+            "xoxb-1",
+        ];
+        let err = Cli::try_parse_from(args).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+
+        let cli = Cli::try_parse_from(args.into_iter().chain(["--channel", "#ci"])).unwrap();
+        assert!(cli.into_command().is_ok());
+    }
+
+    #[test]
+    fn test_threads_require_bot_token() {
+        let cli = Cli::try_parse_from([
+            "junit_to_slack_notification",
+            "--webhook-url",
+            "https://hooks.slack.com/services/T/B/X",
+            "--overflow",
+            "thread",
+        ])
+        .unwrap();
+        let err = cli.into_command().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_clusters_reject_list_options() {
+        let args = [
+            "junit_to_slack_notification",
+            "summarize",
+            "--layout",
+            "clusters",
+        ];
+        assert!(Cli::try_parse_from(args).unwrap().into_command().is_ok());
+
+        for option in [["--group-by", "suite"], ["--overflow", "follow-up"]] {
+            let cli = Cli::try_parse_from(args.into_iter().chain(option)).unwrap();
+            let err = cli.into_command().err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn test_smtp_auth_requires_tls() {
+        let args = [
+            "junit_to_slack_notification",
+            "--smtp-host",
+            "localhost",
+            "--email-from",
+            "ci@example.com",
+            "--email-to",
+            "dev@example.com",
+            "--smtp-username",
+            "ci",
+            "--smtp-password",
+            "secret",
+        ];
+        assert!(Cli::try_parse_from(args).unwrap().into_command().is_ok());
+
+        let cli = Cli::try_parse_from(args.into_iter().chain(["--smtp-tls", "none"])).unwrap();
+        let err = cli.into_command().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_recovery_requires_history_file() {
+        let args = [
+            "junit_to_slack_notification",
+            "--webhook-url",
+            "https://hooks.slack.com/services/T/B/X",
+            "--notify-on-recovery",
+        ];
+        let err = Cli::try_parse_from(args)
+            .unwrap()
+            .into_command()
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+
+        let cli =
+            Cli::try_parse_from(args.into_iter().chain(["--history-file", "runs.jsonl"])).unwrap();
+        assert!(cli.into_command().is_ok());
+    }
+
+    #[test]
+    fn test_diff_does_not_require_slack() {
+        let cli = Cli::try_parse_from([
+            "junit_to_slack_notification",
+            "diff",
+            "--baseline",
+            "main/*.xml",
+            "current.xml",
+        ])
+        .unwrap();
+        let Ok(Command::Diff(args)) = cli.into_command() else {
+            panic!("expected the diff command");
+        };
+        assert_eq!(args.baseline, vec!["main/*.xml"]);
+        assert_eq!(args.report.inputs, vec!["current.xml"]);
+        assert!(args.notifiers.is_empty());
+
+        assert!(Cli::try_parse_from(["junit_to_slack_notification", "diff"]).is_err());
+    }
+
+    #[test]
+    fn test_diff_threads_require_bot_token() {
+        let cli = Cli::try_parse_from([
+            "junit_to_slack_notification",
+            "diff",
+            "--baseline",
+            "main/*.xml",
+            "--threaded",
+        ])
+        .unwrap();
+        let err = cli.into_command().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_rejects_invalid_flags() {
+        assert!(Cli::try_parse_from([
+            "junit_to_slack_notification",
+            "send",
+            "--webhook-url",
+            "not a url"
+        ])
+        .is_err());
+        assert!(
+            Cli::try_parse_from(["junit_to_slack_notification", "summarize", "--title", " "])
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use crate::FailedTest;
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Number of stack frames that are part of a signature.
+const SIGNATURE_FRAMES: usize = 3;
+
+static UUID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}")
+        .unwrap()
+});
+static PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:[A-Za-z]:)?(?:[\\/][\w.\-]+){2,}[\\/]?").unwrap());
+static HEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b0[xX][0-9a-fA-F]+\b|\b[0-9a-fA-F]{8,}\b").unwrap());
+static NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d+").unwrap());
+
+/// Failures that share a signature, most likely from the same root cause.
+pub struct Cluster<'a> {
+    pub signature: String,
+    /// In report order; the first one stands for the whole cluster.
+    pub tests: Vec<&'a FailedTest>,
+}
+
+/// Groups failures by [`signature`], largest cluster first. Clusters of the
+/// same size keep the order in which they first failed.
+pub fn cluster_failures(failed_tests: &[FailedTest]) -> Vec<Cluster<'_>> {
+    let mut clusters: Vec<Cluster> = vec![];
+    for failed in failed_tests {
+        let signature = signature(failed);
+        match clusters
+            .iter_mut()
+            .find(|cluster| cluster.signature == signature)
+        {
+            Some(cluster) => cluster.tests.push(failed),
+            None => clusters.push(Cluster {
+                signature,
+                tests: vec![failed],
+            }),
+        }
+    }
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.tests.len()));
+    clusters
+}
+
+/// Identifies a failure by its type, its message and its top stack frames,
+/// with the parts that vary between runs of the same bug (numbers, hex
+/// values, paths and UUIDs) replaced by placeholders.
+pub fn signature(failed: &FailedTest) -> String {
+    let Some(details) = failed.details() else {
+        return String::new();
+    };
+    let frames: Vec<&str> = details
+        .text
+        .lines()
+        .map(str::trim)
+        .filter(|line| is_frame(line))
+        .take(SIGNATURE_FRAMES)
+        .collect();
+    [
+        details.failure_type.trim(),
+        details.message.trim(),
+        frames.join("\n").as_str(),
+    ]
+    .map(normalize)
+    .join("\n")
+}
+
+/// Whether a stack trace line names a frame, as in Java (`at …`), Python
+/// (`File "…", line …`) or JavaScript traces.
+fn is_frame(line: &str) -> bool {
+    line.starts_with("at ") || line.starts_with("File \"")
+}
+
+fn normalize(text: &str) -> String {
+    let text = UUID.replace_all(text, "<uuid>");
+    let text = PATH.replace_all(&text, "<path>");
+    let text = HEX.replace_all(&text, "<hex>");
+    NUMBER.replace_all(&text, "<n>").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use junit_parser::{TestFailure, TestStatus};
+
+    fn failed_test(name: &str, message: &str, text: &str) -> FailedTest {
+        let mut failed = fixtures::failed_test(name);
+        failed.case.status = TestStatus::Failure(TestFailure {
+            message: message.to_string(),
+            text: text.to_string(),
+            failure_type: "IOException".to_string(),
+        });
+        failed
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(
+                "Upload 3f2b8c1e-1d2a-4b5c-8d9e-0a1b2c3d4e5f to /tmp/run-42/out.bin failed at 0x7ffd after 1500ms (deadbeefcafe)"
+            ),
+            "Upload <uuid> to <path> failed at <hex> after <n>ms (<hex>)"
+        );
+    }
+
+    #[test]
+    fn test_cluster_failures_by_signature() {
+        let trace = |line: u32| {
+            format!(
+                "IOException: Connection refused\n\tat Db.connect(Db.java:{})\n\tat Fixture.setUp(Fixture.java:7)",
+                line
+            )
+        };
+        let failed_tests = vec![
+            failed_test("a", "expected 1 but was 2", "at A.a(A.java:3)"),
+            failed_test("b", "Connection refused: localhost:5432", &trace(10)),
+            failed_test("c", "Connection refused: localhost:5433", &trace(11)),
+            failed_test("d", "Connection refused: localhost:5432", "at Other.call"),
+        ];
+
+        let clusters = cluster_failures(&failed_tests);
+        let names: Vec<Vec<&str>> = clusters
+            .iter()
+            .map(|cluster| {
+                cluster
+                    .tests
+                    .iter()
+                    .map(|failed| failed.case.name.as_str())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(names, vec![vec!["b", "c"], vec!["a"], vec!["d"]]);
+        assert_eq!(
+            clusters[0].signature,
+            "IOException\nConnection refused: localhost:<n>\nat Db.connect(Db.java:<n>)\nat Fixture.setUp(Fixture.java:<n>)"
+        );
+    }
+}
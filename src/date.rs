@@ -0,0 +1,58 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml::value::{Date, Datetime};
+
+/// Today's date in UTC.
+pub fn today() -> Date {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86_400) as i64;
+    civil_from_days(days)
+}
+
+/// The date of a TOML value that must be a plain local date, such as
+/// `2026-12-31`.
+pub fn local_date(value: Datetime) -> Result<Date, String> {
+    match value {
+        Datetime {
+            date: Some(date),
+            time: None,
+            offset: None,
+        } => Ok(date),
+        other => Err(format!("expected a date, not {}", other)),
+    }
+}
+
+/// Converts days since 1970-01-01 into a Gregorian calendar date, following
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> Date {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    Date {
+        year: year as u16,
+        month: month as u8,
+        day: day as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: u16, month: u8, day: u8) -> Date {
+        Date { year, month, day }
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), date(1970, 1, 1));
+        assert_eq!(civil_from_days(19_782), date(2024, 2, 29));
+        assert_eq!(civil_from_days(20_744), date(2026, 10, 18));
+    }
+}
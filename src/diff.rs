@@ -0,0 +1,197 @@
+use crate::flaky;
+use crate::input::ReportFile;
+use junit_parser::{TestCase, TestStatus, TestSuite};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Final result of a test case, ignoring failure details.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl Outcome {
+    fn of(case: &TestCase) -> Self {
+        match case.status {
+            TestStatus::Success => Outcome::Passed,
+            TestStatus::Failure(_) | TestStatus::Error(_) => Outcome::Failed,
+            TestStatus::Skipped(_) => Outcome::Skipped,
+        }
+    }
+}
+
+/// How the tests of a current run changed relative to a baseline run.
+///
+/// Test cases are matched by their `classname` and `name` attributes and
+/// listed by their display name, sorted.
+#[derive(Debug, Default, PartialEq)]
+pub struct Comparison {
+    /// Failing now, but passed or was skipped in the baseline or did not
+    /// exist there.
+    pub newly_failing: Vec<String>,
+    /// Passing now after failing in the baseline.
+    pub newly_passing: Vec<String>,
+    /// Skipped now after running in the baseline.
+    pub newly_skipped: Vec<String>,
+    /// Not part of the baseline.
+    pub added: Vec<String>,
+    /// Part of the baseline only.
+    pub removed: Vec<String>,
+}
+
+impl Comparison {
+    pub fn new(baseline: &[ReportFile], current: &[ReportFile]) -> Self {
+        let baseline = outcomes(baseline);
+        let current = outcomes(current);
+        let mut comparison = Comparison::default();
+        for (key, (name, outcome)) in &current {
+            let before = baseline.get(key).map(|(_, outcome)| *outcome);
+            if before.is_none() {
+                comparison.added.push(name.clone());
+            }
+            match (before, outcome) {
+                (Some(Outcome::Failed), Outcome::Failed) => {}
+                (_, Outcome::Failed) => comparison.newly_failing.push(name.clone()),
+                (Some(Outcome::Failed), Outcome::Passed) => {
+                    comparison.newly_passing.push(name.clone())
+                }
+                (Some(Outcome::Passed | Outcome::Failed), Outcome::Skipped) => {
+                    comparison.newly_skipped.push(name.clone())
+                }
+                _ => {}
+            }
+        }
+        comparison.removed = baseline
+            .iter()
+            .filter(|(key, _)| !current.contains_key(*key))
+            .map(|(_, (name, _))| name.clone())
+            .collect();
+        comparison
+    }
+
+    pub fn has_regressions(&self) -> bool {
+        !self.newly_failing.is_empty()
+    }
+
+    /// One line with the number of tests in each category.
+    pub fn counts(&self) -> String {
+        format!(
+            "{} newly failing, {} newly passing, {} newly skipped, {} added, {} removed",
+            self.newly_failing.len(),
+            self.newly_passing.len(),
+            self.newly_skipped.len(),
+            self.added.len(),
+            self.removed.len()
+        )
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Compared with the baseline: {}", self.counts())?;
+        for (heading, names) in [
+            ("Newly failing", &self.newly_failing),
+            ("Newly passing", &self.newly_passing),
+            ("Newly skipped", &self.newly_skipped),
+            ("Added", &self.added),
+            ("Removed", &self.removed),
+        ] {
+            if names.is_empty() {
+                continue;
+            }
+            write!(f, "\n\n{} ({}):", heading, names.len())?;
+            for name in names {
+                write!(f, "\n- {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of every test case across `reports`, keyed by classname and
+/// name. A test that appears more than once counts as failed if any of its
+/// runs failed.
+fn outcomes(reports: &[ReportFile]) -> BTreeMap<(String, String), (String, Outcome)> {
+    let mut outcomes = BTreeMap::new();
+    for report in reports {
+        collect_outcomes(&report.suites, &mut outcomes);
+    }
+    outcomes
+}
+
+fn collect_outcomes(
+    suites: &[TestSuite],
+    outcomes: &mut BTreeMap<(String, String), (String, Outcome)>,
+) {
+    for suite in suites {
+        collect_outcomes(&suite.suites, outcomes);
+        for case in &suite.cases {
+            let outcome = Outcome::of(case);
+            outcomes
+                .entry(flaky::rerun_key(case))
+                .and_modify(|(_, existing)| {
+                    if outcome == Outcome::Failed {
+                        *existing = outcome;
+                    }
+                })
+                .or_insert_with(|| (case.name.clone(), outcome));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn report(xml: &str) -> ReportFile {
+        ReportFile {
+            path: PathBuf::from("junit.xml"),
+            suites: junit_parser::from_reader(xml.as_bytes()).unwrap().suites,
+            reruns: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_compare_reports() {
+        let baseline = report(
+            r#"<testsuite name="s">
+                <testcase classname="A" name="still_passing"/>
+                <testcase classname="A" name="broken"/>
+                <testcase classname="A" name="fixed"><failure/></testcase>
+                <testcase classname="A" name="still_failing"><error/></testcase>
+                <testcase classname="A" name="disabled"/>
+                <testcase classname="A" name="deleted"/>
+            </testsuite>"#,
+        );
+        let current = report(
+            r#"<testsuite name="s">
+                <testcase classname="A" name="still_passing"/>
+                <testcase classname="A" name="broken"><failure/></testcase>
+                <testcase classname="A" name="fixed"/>
+                <testcase classname="A" name="still_failing"><error/></testcase>
+                <testcase classname="A" name="disabled"><skipped/></testcase>
+                <testcase classname="B" name="deleted"/>
+                <testcase classname="A" name="new"><failure/></testcase>
+            </testsuite>"#,
+        );
+
+        let comparison = Comparison::new(&[baseline], &[current]);
+        assert_eq!(
+            comparison,
+            Comparison {
+                newly_failing: vec!["A::broken".to_string(), "A::new".to_string()],
+                newly_passing: vec!["A::fixed".to_string()],
+                newly_skipped: vec!["A::disabled".to_string()],
+                added: vec!["A::new".to_string(), "B::deleted".to_string()],
+                removed: vec!["A::deleted".to_string()],
+            }
+        );
+        assert!(comparison.has_regressions());
+        assert!(comparison.to_string().starts_with(
+            "Compared with the baseline: 2 newly failing, 1 newly passing, 1 newly skipped, 2 added, 1 removed\n\nNewly failing (2):\n- A::broken\n- A::new"
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+use crate::cli::MessageArgs;
+use crate::diff::Comparison;
+use crate::error::Result;
+use crate::http::HttpClient;
+use crate::notifier::{
+    self, escape_markdown, ListItem, ListSection, Notifier, ALL_PASSED, BACK_TO_GREEN,
+};
+use crate::summary::{format_duration, format_pass_rate, Summary};
+use crate::{FailedTest, TestRun};
+use reqwest::Url;
+use serde::Serialize;
+
+const RED: u32 = 0xE0_1E_5A;
+const GREEN: u32 = 0x2E_B6_7D;
+
+/// Discord rejects embeds with more fields than this.
+const MAX_FIELDS: usize = 25;
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 4_096;
+const MAX_FIELD_NAME_LENGTH: usize = 256;
+const MAX_FIELD_VALUE_LENGTH: usize = 1_024;
+/// Discord rejects messages whose embeds add up to more characters than this.
+const MAX_EMBED_LENGTH: usize = 6_000;
+
+/// Payload accepted by Discord webhooks.
+#[derive(Serialize)]
+pub struct DiscordMessage {
+    embeds: Vec<Embed>,
+    /// Test output must not ping `@everyone` or anyone else.
+    allowed_mentions: AllowedMentions,
+}
+
+#[derive(Serialize)]
+struct AllowedMentions {
+    parse: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Embed {
+    title: String,
+    description: String,
+    color: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<Field>,
+}
+
+impl Embed {
+    fn new(title: &str, description: String, color: u32) -> Self {
+        Embed {
+            title: notifier::truncate(title, MAX_TITLE_LENGTH),
+            description: notifier::truncate(&description, MAX_DESCRIPTION_LENGTH),
+            color,
+            fields: vec![],
+        }
+    }
+
+    /// Characters that count towards Discord's limit on the embed.
+    fn length(&self) -> usize {
+        self.title.chars().count()
+            + self.description.chars().count()
+            + self.fields.iter().map(Field::length).sum::<usize>()
+    }
+
+    fn push_field(&mut self, name: &str, value: &str, inline: bool) {
+        self.fields.push(Field::new(name, value, inline));
+    }
+}
+
+#[derive(Serialize)]
+pub struct Field {
+    name: String,
+    value: String,
+    inline: bool,
+}
+
+impl Field {
+    fn new(name: &str, value: &str, inline: bool) -> Self {
+        Field {
+            name: notifier::truncate(name, MAX_FIELD_NAME_LENGTH),
+            value: notifier::truncate(value, MAX_FIELD_VALUE_LENGTH),
+            inline,
+        }
+    }
+
+    fn length(&self) -> usize {
+        self.name.chars().count() + self.value.chars().count()
+    }
+}
+
+/// Posts embeds to a Discord channel through a webhook.
+pub struct DiscordNotifier<'a> {
+    url: Url,
+    client: &'a HttpClient,
+}
+
+impl<'a> DiscordNotifier<'a> {
+    pub fn new(url: Url, client: &'a HttpClient) -> Self {
+        DiscordNotifier { url, client }
+    }
+
+    fn send(&self, embed: Embed) -> Result<()> {
+        let message = DiscordMessage {
+            embeds: vec![embed],
+            allowed_mentions: AllowedMentions { parse: vec![] },
+        };
+        self.client
+            .post_json(self.url.as_str(), &message, "Discord")?;
+        println!("Results sent to Discord");
+        Ok(())
+    }
+}
+
+impl Notifier for DiscordNotifier<'_> {
+    fn notify(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(build_embed(run, args))
+    }
+
+    fn notify_recovery(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(build_recovery_embed(run, args))
+    }
+
+    fn notify_regressions(
+        &self,
+        run: &TestRun,
+        comparison: &Comparison,
+        args: &MessageArgs,
+    ) -> Result<()> {
+        self.send(build_regression_embed(run, comparison, args))
+    }
+}
+
+/// Builds an embed colored by outcome, with the counts as inline fields and
+/// one field per failure, up to `--max-failures` and as many as fit in
+/// Discord's limits. The remaining failures are counted in a last field.
+///
+/// Flaky tests, quarantined failures and tests fixed since the previous run
+/// get a field each after the failures.
+pub fn build_embed(run: &TestRun, args: &MessageArgs) -> Embed {
+    build_embed_with_sections(run, args, vec![])
+}
+
+/// Lists the newly failing tests like [`build_embed`], and counts every kind
+/// of change since the baseline in a last field.
+pub fn build_regression_embed(run: &TestRun, comparison: &Comparison, args: &MessageArgs) -> Embed {
+    let counts = Field::new("Compared with the baseline", &comparison.counts(), false);
+    build_embed_with_sections(run, args, vec![counts])
+}
+
+/// Like [`build_embed`], with `extra` fields added after the other sections.
+fn build_embed_with_sections(run: &TestRun, args: &MessageArgs, extra: Vec<Field>) -> Embed {
+    let summary = &run.summary;
+    let (description, color) = if summary.all_passed() {
+        (format!("{}\n{}", summary, ALL_PASSED), GREEN)
+    } else {
+        (summary.to_string(), RED)
+    };
+    let mut embed = Embed::new(&args.title, description, color);
+    push_count_fields(&mut embed, summary);
+
+    let mut sections: Vec<Field> = notifier::list_sections(run)
+        .iter()
+        .map(list_field)
+        .collect();
+    sections.extend(extra);
+
+    // Keep room for the sections and the "…and N more" field.
+    let max_fields = MAX_FIELDS - sections.len() - 1;
+    let max_length = MAX_EMBED_LENGTH
+        - sections.iter().map(Field::length).sum::<usize>()
+        - "…and 1000 more failures".len();
+    let mut listed = 0;
+    for failed in run.failed_tests.iter().take(args.max_failures) {
+        let field = failure_field(failed, args.stack_trace_lines);
+        if embed.fields.len() == max_fields || embed.length() + field.length() > max_length {
+            break;
+        }
+        embed.fields.push(field);
+        listed += 1;
+    }
+    let remaining = run.failed_tests.len() - listed;
+    if remaining > 0 {
+        embed.push_field(
+            &format!("…and {} more failures", remaining),
+            "\u{200b}",
+            false,
+        );
+    }
+    embed.fields.extend(sections);
+    embed
+}
+
+/// A green embed for a run that passes again, with a field listing the
+/// tests it fixed.
+pub fn build_recovery_embed(run: &TestRun, args: &MessageArgs) -> Embed {
+    let description = format!("{}\n{}", run.summary, BACK_TO_GREEN);
+    let mut embed = Embed::new(&args.title, description, GREEN);
+    push_count_fields(&mut embed, &run.summary);
+    embed
+        .fields
+        .extend(notifier::recovery_sections(run).iter().map(list_field));
+    embed
+}
+
+fn push_count_fields(embed: &mut Embed, summary: &Summary) {
+    embed.push_field("Passed", &summary.passed.to_string(), true);
+    embed.push_field("Failed", &summary.failed.to_string(), true);
+    embed.push_field("Errored", &summary.errored.to_string(), true);
+    embed.push_field("Skipped", &summary.skipped.to_string(), true);
+    if let Some(pass_rate) = summary.pass_rate() {
+        embed.push_field("Pass rate", &format_pass_rate(pass_rate), true);
+    }
+    embed.push_field("Duration", &format_duration(summary.duration), true);
+}
+
+/// A failed test named by the field, with its reason and the top of its
+/// stack trace as the value.
+fn failure_field(failed: &FailedTest, stack_trace_lines: usize) -> Field {
+    let name = escape_markdown(&failed.case.name);
+    let name = match failed.history {
+        Some(status) => format!("❌ {} ({})", name, status),
+        None => format!("❌ {}", name),
+    };
+    let mut value = escape_markdown(&failed.reason());
+    if let Some(issue) = &failed.known_issue {
+        let label = escape_markdown(&issue.label);
+        value.push_str(&match &issue.url {
+            Some(url) => format!("\nKnown issue: [{}]({})", label, url),
+            None => format!("\nKnown issue: {}", label),
+        });
+    }
+    let stack_trace = failed.stack_trace(stack_trace_lines).join("\n");
+    // Leave room to close the code block if the value gets truncated.
+    let max_stack_trace = MAX_FIELD_VALUE_LENGTH.saturating_sub(value.chars().count() + 10);
+    if !stack_trace.is_empty() && max_stack_trace > 0 {
+        value.push_str(&format!(
+            "\n```\n{}\n```",
+            notifier::truncate(&stack_trace, max_stack_trace)
+        ));
+    }
+    if value.is_empty() {
+        value.push('\u{200b}');
+    }
+    Field::new(&name, &value, false)
+}
+
+/// The entry with its note linked to the item's URL, if it has one.
+fn format_item(item: &ListItem) -> String {
+    let name = escape_markdown(item.name);
+    match (&item.note, item.link) {
+        (Some(note), Some(url)) => format!("{} ([{}]({}))", name, escape_markdown(note), url),
+        (Some(note), None) => format!("{} ({})", name, escape_markdown(note)),
+        _ => name,
+    }
+}
+
+/// A field naming the list and its size, with one line per entry.
+fn list_field(list: &ListSection) -> Field {
+    let lines: Vec<String> = list
+        .items
+        .iter()
+        .map(|item| format!("• {}", format_item(item)))
+        .collect();
+    Field::new(&list.heading(), &lines.join("\n"), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{message_args, test_run, test_run_with_stack_trace};
+    use crate::http::tests::test_client;
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    #[test]
+    fn test_build_embed() {
+        let mut run = test_run(1);
+        run.failed_tests[0].case.name = "test_[a]*b*".to_string();
+        let embed = serde_json::to_value(build_embed(&run, &message_args(20))).unwrap();
+        assert_eq!(embed["title"], "Nightly");
+        assert_eq!(embed["color"], RED);
+        assert_eq!(
+            embed["fields"][1],
+            json!({"name": "Failed", "value": "1", "inline": true})
+        );
+        assert_eq!(embed["fields"][6]["name"], r"❌ test\_\[a\]\*b\*");
+        assert!(embed["fields"][6]["value"].as_str().unwrap().starts_with(
+            "AssertionError: expected <1> but was <2>\n```\nAssertionError\n\tat test_0\n```"
+        ));
+
+        let passed = TestRun {
+            summary: Summary {
+                total: 1,
+                passed: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let embed = build_embed(&passed, &message_args(20));
+        assert_eq!(embed.color, GREEN);
+        assert!(embed.description.ends_with(ALL_PASSED));
+    }
+
+    #[test]
+    fn test_build_embed_stays_within_limits() {
+        let args = MessageArgs {
+            stack_trace_lines: 3,
+            ..message_args(100)
+        };
+        let embed = build_embed(&test_run_with_stack_trace(100, 400), &args);
+        assert!(embed.fields.len() <= MAX_FIELDS);
+        assert!(embed.length() <= MAX_EMBED_LENGTH);
+        let listed = embed
+            .fields
+            .iter()
+            .filter(|field| field.name.starts_with('❌'))
+            .count();
+        assert_eq!(
+            embed.fields.last().unwrap().name,
+            format!("…and {} more failures", 100 - listed)
+        );
+    }
+
+    #[test]
+    fn test_discord_notifier_posts_embeds() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/webhook")
+            .match_body(Matcher::PartialJson(json!({
+                "embeds": [{"title": "Nightly", "color": RED}],
+                "allowed_mentions": {"parse": []},
+            })))
+            .with_status(204)
+            .create();
+        let client = test_client();
+        let url = format!("{}/webhook", server.url()).parse().unwrap();
+
+        DiscordNotifier::new(url, &client)
+            .notify(&test_run(2), &message_args(20))
+            .unwrap();
+        mock.assert();
+    }
+}
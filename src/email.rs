@@ -0,0 +1,462 @@
+use crate::cli::{EmailArgs, MessageArgs, SmtpTls};
+use crate::diff::Comparison;
+use crate::error::{Error, Result};
+use crate::notifier::{self, escape_html, ListSection, Notifier, ALL_PASSED, BACK_TO_GREEN};
+use crate::retry::{Attempt, RetryPolicy};
+use crate::summary::{format_duration, format_pass_rate, Summary};
+use crate::{FailedTest, TestRun};
+use lettre::message::MultiPart;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{Message, SmtpTransport, Transport};
+use std::time::Duration;
+
+/// An email with the same content as HTML and as plain text.
+pub struct Email {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Sends the results by email through an SMTP server, retrying like the
+/// other services. `timeout` applies to each step of the SMTP session.
+pub struct EmailNotifier<'a> {
+    args: &'a EmailArgs,
+    retry_policy: &'a RetryPolicy,
+    timeout: Duration,
+}
+
+impl<'a> EmailNotifier<'a> {
+    pub fn new(args: &'a EmailArgs, retry_policy: &'a RetryPolicy, timeout: Duration) -> Self {
+        EmailNotifier {
+            args,
+            retry_policy,
+            timeout,
+        }
+    }
+
+    fn transport(&self) -> Result<SmtpTransport> {
+        let host = self
+            .args
+            .smtp_host
+            .as_deref()
+            .expect("email is only sent with --smtp-host");
+        let tls = |host: &str| {
+            TlsParameters::new(host.to_string())
+                .map_err(|err| Error::Config(format!("Invalid SMTP host {}: {}", host, err)))
+        };
+        let (tls, port) = match self.args.smtp_tls {
+            SmtpTls::Starttls => (Tls::Required(tls(host)?), 587),
+            SmtpTls::Implicit => (Tls::Wrapper(tls(host)?), 465),
+            SmtpTls::None => (Tls::None, 25),
+        };
+        let mut builder = SmtpTransport::builder_dangerous(host)
+            .port(self.args.smtp_port.unwrap_or(port))
+            .tls(tls)
+            .timeout(Some(self.timeout));
+        if let (Some(username), Some(password)) =
+            (&self.args.smtp_username, &self.args.smtp_password)
+        {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(builder.build())
+    }
+
+    fn send(&self, email: Email) -> Result<()> {
+        let mut message = Message::builder().subject(email.subject).from(
+            self.args
+                .email_from
+                .clone()
+                .expect("email is only sent with --email-from"),
+        );
+        for to in &self.args.email_to {
+            message = message.to(to.clone());
+        }
+        let message = message
+            .multipart(MultiPart::alternative_plain_html(email.text, email.html))
+            .map_err(|err| Error::Config(format!("Invalid email: {}", err)))?;
+        let transport = self.transport()?;
+        // Transient (4xx) replies and timeouts are worth retrying; other
+        // errors, such as a rejected recipient, would fail again.
+        self.retry_policy.retry(|| match transport.send(&message) {
+            Ok(_) => Attempt::Done(()),
+            Err(err) => {
+                let retryable = err.is_transient() || err.is_timeout();
+                let error = Error::delivery(format!("Failed to send email: {}", err));
+                if retryable {
+                    Attempt::Retry(error, None)
+                } else {
+                    Attempt::Fail(error)
+                }
+            }
+        })?;
+        println!("Results sent by email");
+        Ok(())
+    }
+}
+
+impl Notifier for EmailNotifier<'_> {
+    fn notify(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(build_email(run, args))
+    }
+
+    fn notify_recovery(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(build_recovery_email(run, args))
+    }
+
+    fn notify_regressions(
+        &self,
+        run: &TestRun,
+        comparison: &Comparison,
+        args: &MessageArgs,
+    ) -> Result<()> {
+        let mut email = build_email(run, args);
+        email.subject = format!(
+            "{}: {} newly failing",
+            args.title,
+            comparison.newly_failing.len()
+        );
+        email.html.push_str(&format!(
+            "<h3>Compared with the baseline</h3>\n<p>{}</p>\n",
+            escape_html(&comparison.counts())
+        ));
+        email.text.push_str(&format!(
+            "\nCompared with the baseline\n{}\n",
+            comparison.counts()
+        ));
+        self.send(email)
+    }
+}
+
+/// Builds an email with the summary and a table of the failures, up to
+/// `--max-failures`, each with the top of its stack trace. Flaky tests,
+/// quarantined failures and tests fixed since the previous run are listed
+/// at the end.
+pub fn build_email(run: &TestRun, args: &MessageArgs) -> Email {
+    let failures = run.failed_tests.len();
+    let subject = if run.summary.all_passed() {
+        format!("{}: all tests passed", args.title)
+    } else {
+        format!(
+            "{}: {} failed",
+            args.title,
+            run.summary.failed + run.summary.errored
+        )
+    };
+    let mut email = Email {
+        subject,
+        html: html_header(&args.title, &run.summary),
+        text: text_header(&args.title, &run.summary),
+    };
+    if run.summary.all_passed() {
+        email.html.push_str(&format!("<p>{}</p>\n", ALL_PASSED));
+        email.text.push_str(&format!("{}\n", ALL_PASSED));
+    }
+
+    let listed = &run.failed_tests[..failures.min(args.max_failures)];
+    if !listed.is_empty() {
+        email.html.push_str(&format!(
+            "<h3>Failed tests ({})</h3>\n\
+             <table border=\"1\" cellpadding=\"4\" cellspacing=\"0\">\n\
+             <tr><th>Test</th><th>Failure</th><th>Report</th></tr>\n",
+            failures
+        ));
+        email
+            .text
+            .push_str(&format!("\nFailed tests ({}):\n", failures));
+        for failed in listed {
+            email
+                .html
+                .push_str(&html_row(failed, args.stack_trace_lines));
+            email
+                .text
+                .push_str(&text_entry(failed, args.stack_trace_lines));
+        }
+        email.html.push_str("</table>\n");
+    }
+    let remaining = failures - listed.len();
+    if remaining > 0 {
+        email
+            .html
+            .push_str(&format!("<p>…and {} more</p>\n", remaining));
+        email.text.push_str(&format!("…and {} more\n", remaining));
+    }
+
+    for section in notifier::list_sections(run) {
+        append_list(&mut email, &section, args.max_failures);
+    }
+    email
+}
+
+/// An email for a run that passes again, with the subject saying so and the
+/// body listing the tests it fixed.
+pub fn build_recovery_email(run: &TestRun, args: &MessageArgs) -> Email {
+    let mut email = Email {
+        subject: format!("{}: back to green", args.title),
+        html: html_header(&args.title, &run.summary),
+        text: text_header(&args.title, &run.summary),
+    };
+    email.html.push_str(&format!("<p>{}</p>\n", BACK_TO_GREEN));
+    email.text.push_str(&format!("{}\n", BACK_TO_GREEN));
+    for section in notifier::recovery_sections(run) {
+        append_list(&mut email, &section, args.max_failures);
+    }
+    email
+}
+
+fn html_header(title: &str, summary: &Summary) -> String {
+    let pass_rate = summary
+        .pass_rate()
+        .map_or("-".to_string(), format_pass_rate);
+    format!(
+        "<h2>{}</h2>\n\
+         <table border=\"1\" cellpadding=\"4\" cellspacing=\"0\">\n\
+         <tr><th>Total</th><th>Passed</th><th>Failed</th><th>Errored</th><th>Skipped</th><th>Flaky</th><th>Pass rate</th><th>Duration</th></tr>\n\
+         <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n\
+         </table>\n",
+        escape_html(title),
+        summary.total,
+        summary.passed,
+        summary.failed,
+        summary.errored,
+        summary.skipped,
+        summary.flaky,
+        pass_rate,
+        format_duration(summary.duration)
+    )
+}
+
+fn text_header(title: &str, summary: &Summary) -> String {
+    format!("{}\n{}\n", title, summary)
+}
+
+fn html_row(failed: &FailedTest, stack_trace_lines: usize) -> String {
+    let mut name = format!("<b>{}</b>", escape_html(&failed.case.name));
+    if let Some(status) = failed.history {
+        name.push_str(&format!(" <i>{}</i>", status));
+    }
+    let mut reason = escape_html(&failed.reason());
+    if let Some(issue) = &failed.known_issue {
+        let label = escape_html(&issue.label);
+        reason.push_str(&match &issue.url {
+            Some(url) => format!(
+                "<br>Known issue: <a href=\"{}\">{}</a>",
+                escape_html(url),
+                label
+            ),
+            None => format!("<br>Known issue: {}", label),
+        });
+    }
+    let stack_trace = failed.stack_trace(stack_trace_lines);
+    if !stack_trace.is_empty() {
+        reason.push_str(&format!(
+            "<pre>{}</pre>",
+            escape_html(&stack_trace.join("\n"))
+        ));
+    }
+    format!(
+        "<tr><td>{}</td><td>{}</td><td><code>{}</code></td></tr>\n",
+        name,
+        reason,
+        escape_html(&failed.source.display().to_string())
+    )
+}
+
+fn text_entry(failed: &FailedTest, stack_trace_lines: usize) -> String {
+    let mut entry = format!("- {}", failed.case.name);
+    if let Some(status) = failed.history {
+        entry.push_str(&format!(" [{}]", status));
+    }
+    entry.push_str(&format!(" ({})\n", failed.source.display()));
+    let reason = failed.reason();
+    if !reason.is_empty() {
+        entry.push_str(&format!("  {}\n", reason));
+    }
+    if let Some(issue) = &failed.known_issue {
+        match &issue.url {
+            Some(url) => entry.push_str(&format!("  Known issue: {} <{}>\n", issue.label, url)),
+            None => entry.push_str(&format!("  Known issue: {}\n", issue.label)),
+        }
+    }
+    for line in failed.stack_trace(stack_trace_lines) {
+        entry.push_str(&format!("    {}\n", line.trim()));
+    }
+    entry
+}
+
+/// Lists up to `max_items` entries of the section under a heading.
+fn append_list(email: &mut Email, section: &ListSection, max_items: usize) {
+    let heading = section.heading();
+    email
+        .html
+        .push_str(&format!("<h3>{}</h3>\n<ul>\n", escape_html(&heading)));
+    email.text.push_str(&format!("\n{}:\n", heading));
+    for item in section.items.iter().take(max_items) {
+        let mut html = escape_html(item.name);
+        let mut text = item.name.to_string();
+        match (&item.note, item.link) {
+            (Some(note), Some(url)) => {
+                html.push_str(&format!(
+                    " (<a href=\"{}\">{}</a>)",
+                    escape_html(url),
+                    escape_html(note)
+                ));
+                text.push_str(&format!(" ({} <{}>)", note, url));
+            }
+            (Some(note), None) => {
+                html.push_str(&format!(" ({})", escape_html(note)));
+                text.push_str(&format!(" ({})", note));
+            }
+            _ => {}
+        }
+        email.html.push_str(&format!("<li>{}</li>\n", html));
+        email.text.push_str(&format!("- {}\n", text));
+    }
+    email.html.push_str("</ul>\n");
+    if section.items.len() > max_items {
+        let more = format!("…and {} more", section.items.len() - max_items);
+        email.html.push_str(&format!("<p>{}</p>\n", more));
+        email.text.push_str(&format!("{}\n", more));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{message_args, test_run};
+    use crate::retry::tests::test_policy;
+    use std::io::{BufRead, BufReader, ErrorKind, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Instant;
+
+    /// How long the sink waits for the client before giving up, so that a
+    /// client that never connects or stalls fails the test instead of hanging.
+    const SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Accepts one SMTP session on a local port and returns the commands and
+    /// message data it received.
+    fn smtp_sink() -> (u16, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        listener.set_nonblocking(true).unwrap();
+        let handle = thread::spawn(move || {
+            let deadline = Instant::now() + SINK_TIMEOUT;
+            let stream = loop {
+                match listener.accept() {
+                    Ok((stream, _)) => break stream,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        assert!(Instant::now() < deadline, "no SMTP client connected");
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                    Err(err) => panic!("{}", err),
+                }
+            };
+            stream.set_nonblocking(false).unwrap();
+            stream.set_read_timeout(Some(SINK_TIMEOUT)).unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut received = vec![];
+            writer.write_all(b"220 localhost ESMTP sink\r\n").unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        received.push(line);
+                        continue;
+                    }
+                } else if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250 AUTH PLAIN LOGIN\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else if line.starts_with("AUTH") {
+                    b"235 authenticated\r\n"
+                } else {
+                    b"250 ok\r\n"
+                };
+                if !in_data || line == "DATA" {
+                    received.push(line);
+                }
+                writer.write_all(reply).unwrap();
+            }
+            received
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn test_build_email() {
+        let email = build_email(&test_run(2), &message_args(1));
+        assert_eq!(email.subject, "Nightly: 2 failed");
+        assert!(email.html.contains(
+            "<tr><td><b>test_0</b></td>\
+             <td>AssertionError: expected &lt;1&gt; but was &lt;2&gt;<pre>AssertionError\n\tat test_0</pre></td>\
+             <td><code>junit.xml</code></td></tr>"
+        ));
+        assert!(email.html.contains("<p>…and 1 more</p>"));
+        assert_eq!(
+            email.text,
+            "Nightly\n\
+             3 tests: 1 passed, 2 failed, 0 errored, 0 skipped (33.3% pass rate) in 1.0s\n\
+             \nFailed tests (2):\n\
+             - test_0 (junit.xml)\n  AssertionError: expected <1> but was <2>\n    AssertionError\n    at test_0\n\
+             …and 1 more\n"
+        );
+    }
+
+    #[test]
+    fn test_build_email_subject_follows_summary() {
+        // A quarantined failure is no longer listed but still fails the run.
+        let mut run = test_run(1);
+        run.failed_tests.clear();
+        let email = build_email(&run, &message_args(1));
+        assert_eq!(email.subject, "Nightly: 1 failed");
+
+        run.summary.failed = 0;
+        let email = build_email(&run, &message_args(1));
+        assert_eq!(email.subject, "Nightly: all tests passed");
+    }
+
+    #[test]
+    fn test_email_notifier_sends_to_smtp_sink() {
+        let (port, sink) = smtp_sink();
+        let args = EmailArgs {
+            smtp_host: Some("127.0.0.1".to_string()),
+            smtp_port: Some(port),
+            smtp_tls: SmtpTls::None,
+            smtp_username: None,
+            smtp_password: None,
+            email_from: Some("CI <ci@example.com>".parse().unwrap()),
+            email_to: vec![
+                "dev@example.com".parse().unwrap(),
+                "qa@example.com".parse().unwrap(),
+            ],
+        };
+
+        EmailNotifier::new(&args, &test_policy(), Duration::from_secs(5))
+            .notify(&test_run(2), &message_args(1))
+            .unwrap();
+        let received = sink.join().unwrap();
+        let has = |expected: &str| received.iter().any(|line| line.starts_with(expected));
+        assert!(!has("AUTH"));
+        assert!(has("MAIL FROM:<ci@example.com>"));
+        assert!(has("RCPT TO:<dev@example.com>"));
+        assert!(has("RCPT TO:<qa@example.com>"));
+        assert!(has("Subject: Nightly: 2 failed"));
+        assert!(has("Content-Type: multipart/alternative"));
+        assert!(has("Content-Type: text/plain; charset=utf-8"));
+        assert!(has("Content-Type: text/html; charset=utf-8"));
+    }
+}
@@ -0,0 +1,81 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can stop a run, grouped by what the pipeline should do about it.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Invalid settings, reported with the same exit code as clap usage errors.
+    #[error("{0}")]
+    Config(String),
+    #[error("Failed to read {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to parse {}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: junit_parser::Error,
+    },
+    #[error("{message}")]
+    Delivery {
+        message: String,
+        #[source]
+        source: Option<reqwest::Error>,
+    },
+}
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// A settings file that could not be read. Unlike a missing report, this
+    /// is a configuration mistake, such as a typo in the path.
+    pub fn config_file(path: &Path, source: io::Error) -> Self {
+        Error::Config(format!("Failed to read {}: {}", path.display(), source))
+    }
+
+    pub fn not_found(path: impl Into<PathBuf>) -> Self {
+        Error::io(path, io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    pub fn delivery(message: impl Into<String>) -> Self {
+        Error::Delivery {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn request_failed(message: impl Into<String>, source: reqwest::Error) -> Self {
+        Error::Delivery {
+            message: message.into(),
+            source: Some(source),
+        }
+    }
+
+    /// Process exit code for this error.
+    ///
+    /// | Code | Meaning                                 |
+    /// |------|-----------------------------------------|
+    /// | 2    | invalid configuration or command line   |
+    /// | 3    | a report could not be found or read     |
+    /// | 4    | a report is not valid JUnit XML         |
+    /// | 5    | the notification could not be delivered |
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Error::Config(_) => 2,
+            Error::Io { .. } => 3,
+            Error::Parse { .. } => 4,
+            Error::Delivery { .. } => 5,
+        })
+    }
+}
@@ -0,0 +1,164 @@
+use junit_parser::TestCase;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+/// A failed attempt recorded by a test runner that reruns failing tests, e.g.
+/// Maven Surefire's `<flakyFailure>` or `<rerunError>` elements.
+///
+/// junit-parser skips these elements, so they are read separately.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rerun {
+    pub rerun_type: String,
+    pub message: String,
+    pub stack_trace: String,
+}
+
+/// Reruns of every test case in a report, keyed by [`rerun_key`].
+pub type Reruns = HashMap<(String, String), Vec<Rerun>>;
+
+const RERUN_ELEMENTS: [&[u8]; 4] = [
+    b"flakyFailure",
+    b"flakyError",
+    b"rerunFailure",
+    b"rerunError",
+];
+
+/// Identifies a test case by its `classname` and `name` attributes.
+pub fn rerun_key(case: &TestCase) -> (String, String) {
+    (
+        case.classname.clone().unwrap_or_default(),
+        case.original_name.clone(),
+    )
+}
+
+/// Collects the rerun elements of every `<testcase>` in `xml`.
+pub fn parse_reruns(xml: &str) -> Result<Reruns, junit_parser::Error> {
+    let mut reader = Reader::from_str(xml);
+    let mut reruns = Reruns::new();
+    let mut case = None;
+    // The rerun being read and whether we are inside one of its
+    // `<system-out>`/`<system-err>` children, whose text is not a stack trace.
+    let mut current: Option<(Rerun, bool)> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.name().as_ref() == b"testcase" => {
+                case = Some(case_key(&e)?);
+            }
+            Event::End(e) if e.name().as_ref() == b"testcase" => case = None,
+            Event::Start(e) if case.is_some() && is_rerun(e.name().as_ref()) => {
+                current = Some((new_rerun(&e)?, false));
+            }
+            Event::Empty(e) if is_rerun(e.name().as_ref()) => {
+                if let Some(key) = &case {
+                    reruns.entry(key.clone()).or_default().push(new_rerun(&e)?);
+                }
+            }
+            Event::Start(e) if is_output(e.name().as_ref()) => {
+                if let Some((_, in_output)) = &mut current {
+                    *in_output = true;
+                }
+            }
+            Event::End(e) if is_output(e.name().as_ref()) => {
+                if let Some((_, in_output)) = &mut current {
+                    *in_output = false;
+                }
+            }
+            Event::Text(text) => {
+                if let Some((rerun, false)) = &mut current {
+                    rerun.stack_trace.push_str(&text.unescape()?);
+                }
+            }
+            Event::CData(text) => {
+                if let Some((rerun, false)) = &mut current {
+                    rerun.stack_trace.push_str(&String::from_utf8_lossy(&text));
+                }
+            }
+            Event::End(e) if is_rerun(e.name().as_ref()) => {
+                if let (Some(key), Some((mut rerun, _))) = (&case, current.take()) {
+                    rerun.stack_trace = rerun.stack_trace.trim().to_string();
+                    reruns.entry(key.clone()).or_default().push(rerun);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(reruns)
+}
+
+fn is_rerun(name: &[u8]) -> bool {
+    RERUN_ELEMENTS.contains(&name)
+}
+
+fn is_output(name: &[u8]) -> bool {
+    matches!(name, b"system-out" | b"system-err")
+}
+
+fn case_key(e: &BytesStart) -> Result<(String, String), junit_parser::Error> {
+    Ok((
+        attribute(e, b"classname")?.unwrap_or_default(),
+        attribute(e, b"name")?.unwrap_or_default(),
+    ))
+}
+
+fn new_rerun(e: &BytesStart) -> Result<Rerun, junit_parser::Error> {
+    Ok(Rerun {
+        rerun_type: attribute(e, b"type")?.unwrap_or_default(),
+        message: attribute(e, b"message")?.unwrap_or_default(),
+        stack_trace: String::new(),
+    })
+}
+
+fn attribute(e: &BytesStart, name: &[u8]) -> Result<Option<String>, junit_parser::Error> {
+    match e.try_get_attribute(name)? {
+        Some(attribute) => Ok(Some(attribute.unescape_value()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reruns() {
+        let xml = r#"<testsuite name="s">
+            <testcase classname="com.example.A" name="flaky">
+                <flakyFailure message="expected &lt;1&gt;" type="AssertionError">
+                    <stackTrace>at A.flaky(A.java:3)</stackTrace>
+                    <system-out>noise</system-out>
+                </flakyFailure>
+                <flakyError message="timeout" type="TimeoutException"/>
+            </testcase>
+            <testcase classname="com.example.A" name="stable"/>
+            <testcase classname="com.example.A" name="broken">
+                <failure message="boom"/>
+                <rerunFailure message="boom again"/>
+            </testcase>
+        </testsuite>"#;
+
+        let reruns = parse_reruns(xml).unwrap();
+        let flaky = &reruns[&("com.example.A".to_string(), "flaky".to_string())];
+        assert_eq!(
+            flaky,
+            &vec![
+                Rerun {
+                    rerun_type: "AssertionError".to_string(),
+                    message: "expected <1>".to_string(),
+                    stack_trace: "at A.flaky(A.java:3)".to_string(),
+                },
+                Rerun {
+                    rerun_type: "TimeoutException".to_string(),
+                    message: "timeout".to_string(),
+                    stack_trace: String::new(),
+                },
+            ]
+        );
+        assert_eq!(
+            reruns[&("com.example.A".to_string(), "broken".to_string())].len(),
+            1
+        );
+        assert!(!reruns.contains_key(&("com.example.A".to_string(), "stable".to_string())));
+    }
+}
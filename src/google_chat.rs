@@ -0,0 +1,470 @@
+use crate::cli::MessageArgs;
+use crate::diff::Comparison;
+use crate::error::Result;
+use crate::http::HttpClient;
+use crate::notifier::{
+    self, escape_html, ListItem, ListSection, Notifier, ALL_PASSED, BACK_TO_GREEN,
+};
+use crate::summary::{format_duration, format_pass_rate, Summary};
+use crate::{FailedTest, TestRun};
+use reqwest::Url;
+use serde::Serialize;
+
+const RED: &str = "#E01E5A";
+/// Google Chat rejects messages larger than 32,000 bytes; this leaves room
+/// for the markup around the texts.
+const MAX_TEXT_BYTES: usize = 24_000;
+const MAX_WIDGET_TEXT_LENGTH: usize = 2_000;
+/// Widgets of the failed tests section shown before the rest is collapsed.
+const UNCOLLAPSED_FAILURES: usize = 3;
+
+/// Payload accepted by Google Chat incoming webhooks.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleChatMessage {
+    /// Shown in notifications, which do not render cards.
+    text: String,
+    cards_v2: Vec<CardWithId>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CardWithId {
+    card_id: &'static str,
+    card: Card,
+}
+
+#[derive(Serialize)]
+pub struct Card {
+    header: CardHeader,
+    sections: Vec<Section>,
+}
+
+#[derive(Serialize)]
+struct CardHeader {
+    title: String,
+    subtitle: String,
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Section {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    header: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    collapsible: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uncollapsible_widgets_count: Option<usize>,
+    widgets: Vec<Widget>,
+}
+
+/// A card widget. Texts are in Google Chat's subset of HTML.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Widget {
+    #[serde(rename_all = "camelCase")]
+    DecoratedText {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        top_label: Option<String>,
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bottom_label: Option<String>,
+        wrap_text: bool,
+    },
+    TextParagraph {
+        text: String,
+    },
+}
+
+impl Widget {
+    /// A paragraph of HTML, which callers keep within
+    /// `MAX_WIDGET_TEXT_LENGTH` so that no cut breaks its markup.
+    fn paragraph(text: String) -> Self {
+        Widget::TextParagraph { text }
+    }
+
+    /// Bytes of text in the widget.
+    fn length(&self) -> usize {
+        match self {
+            Widget::DecoratedText {
+                top_label,
+                text,
+                bottom_label,
+                ..
+            } => {
+                text.len()
+                    + top_label.as_ref().map_or(0, String::len)
+                    + bottom_label.as_ref().map_or(0, String::len)
+            }
+            Widget::TextParagraph { text } => text.len(),
+        }
+    }
+}
+
+/// Posts cards to a Google Chat space through a webhook.
+pub struct GoogleChatNotifier<'a> {
+    url: Url,
+    client: &'a HttpClient,
+}
+
+impl<'a> GoogleChatNotifier<'a> {
+    /// With a `thread_key`, every message is posted in the thread of that key,
+    /// which is started by the first one.
+    pub fn new(mut url: Url, thread_key: Option<&str>, client: &'a HttpClient) -> Self {
+        if let Some(thread_key) = thread_key {
+            url.query_pairs_mut()
+                .append_pair("threadKey", thread_key)
+                .append_pair("messageReplyOption", "REPLY_MESSAGE_FALLBACK_TO_NEW_THREAD");
+        }
+        GoogleChatNotifier { url, client }
+    }
+
+    fn send(&self, text: String, card: Card) -> Result<()> {
+        let message = GoogleChatMessage {
+            text,
+            cards_v2: vec![CardWithId {
+                card_id: "test-results",
+                card,
+            }],
+        };
+        self.client
+            .post_json(self.url.as_str(), &message, "Google Chat")?;
+        println!("Results sent to Google Chat");
+        Ok(())
+    }
+}
+
+impl Notifier for GoogleChatNotifier<'_> {
+    fn notify(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(fallback_text(run, args), build_card(run, args))
+    }
+
+    fn notify_recovery(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        let text = format!("{}: {}", args.title, BACK_TO_GREEN);
+        self.send(text, build_recovery_card(run, args))
+    }
+
+    fn notify_regressions(
+        &self,
+        run: &TestRun,
+        comparison: &Comparison,
+        args: &MessageArgs,
+    ) -> Result<()> {
+        let mut card = build_card(run, args);
+        card.sections.push(Section {
+            header: Some("Compared with the baseline".to_string()),
+            widgets: vec![Widget::paragraph(escape_html(&comparison.counts()))],
+            ..Default::default()
+        });
+        self.send(fallback_text(run, args), card)
+    }
+}
+
+/// Builds a card with the title and summary in its header, a summary widget
+/// and a collapsible section listing the failed tests, up to
+/// `--max-failures` and Google Chat's size limit.
+///
+/// Flaky tests, quarantined failures and tests fixed since the previous run
+/// get collapsible sections of their own.
+pub fn build_card(run: &TestRun, args: &MessageArgs) -> Card {
+    let mut sections = vec![summary_section(&run.summary)];
+
+    let mut widgets = vec![];
+    let mut length = 0;
+    for failed in run.failed_tests.iter().take(args.max_failures) {
+        let failure = failure_widgets(failed, args.stack_trace_lines);
+        length += failure.iter().map(Widget::length).sum::<usize>();
+        if length > MAX_TEXT_BYTES {
+            break;
+        }
+        widgets.extend(failure);
+    }
+    let listed = widgets
+        .iter()
+        .filter(|widget| matches!(widget, Widget::DecoratedText { .. }))
+        .count();
+    let remaining = run.failed_tests.len() - listed;
+    if remaining > 0 {
+        widgets.push(Widget::paragraph(format!("…and {} more", remaining)));
+    }
+    if !widgets.is_empty() {
+        sections.push(Section {
+            header: Some(format!("Failed tests ({})", run.failed_tests.len())),
+            collapsible: true,
+            uncollapsible_widgets_count: Some(UNCOLLAPSED_FAILURES),
+            widgets,
+        });
+    }
+
+    sections.extend(notifier::list_sections(run).iter().map(list_section));
+
+    Card {
+        header: header(&run.summary, args),
+        sections,
+    }
+}
+
+/// A card for a run that passes again, with a collapsed list of the tests it
+/// fixed.
+pub fn build_recovery_card(run: &TestRun, args: &MessageArgs) -> Card {
+    let mut sections = vec![summary_section(&run.summary)];
+    sections[0]
+        .widgets
+        .push(Widget::paragraph(BACK_TO_GREEN.to_string()));
+    sections.extend(notifier::recovery_sections(run).iter().map(list_section));
+    Card {
+        header: header(&run.summary, args),
+        sections,
+    }
+}
+
+fn header(summary: &Summary, args: &MessageArgs) -> CardHeader {
+    CardHeader {
+        title: args.title.clone(),
+        subtitle: if summary.all_passed() {
+            ALL_PASSED.to_string()
+        } else {
+            format!("{} failed", summary.failed + summary.errored)
+        },
+    }
+}
+
+fn summary_section(summary: &Summary) -> Section {
+    let mut counts = format!(
+        "<b>{}</b> passed, <b>{}</b> failed, <b>{}</b> errored, <b>{}</b> skipped",
+        summary.passed, summary.failed, summary.errored, summary.skipped
+    );
+    if summary.flaky > 0 {
+        counts.push_str(&format!(", <b>{}</b> flaky", summary.flaky));
+    }
+    let mut details = vec![format!("in {}", format_duration(summary.duration))];
+    if let Some(pass_rate) = summary.pass_rate() {
+        details.insert(0, format!("{} pass rate", format_pass_rate(pass_rate)));
+    }
+    Section {
+        widgets: vec![Widget::DecoratedText {
+            top_label: Some(format!("{} tests", summary.total)),
+            text: counts,
+            bottom_label: Some(details.join(" ")),
+            wrap_text: true,
+        }],
+        ..Default::default()
+    }
+}
+
+/// A failed test with its report and reason, followed by the top of its
+/// stack trace.
+fn failure_widgets(failed: &FailedTest, stack_trace_lines: usize) -> Vec<Widget> {
+    let mut name = format!("<b>{}</b>", escape_html(&failed.case.name));
+    if let Some(status) = failed.history {
+        name.push_str(&format!(" <font color=\"{}\">{}</font>", RED, status));
+    }
+    let known_issue = failed.known_issue.as_ref().map_or(String::new(), |issue| {
+        let label = escape_html(&issue.label);
+        match &issue.url {
+            Some(url) => format!(
+                " (known issue: <a href=\"{}\">{}</a>)",
+                escape_html(url),
+                label
+            ),
+            None => format!(" (known issue: {})", label),
+        }
+    });
+    let max_reason = MAX_WIDGET_TEXT_LENGTH.saturating_sub(known_issue.chars().count());
+    let reason = escape_html_within(&failed.reason(), max_reason) + &known_issue;
+    let mut widgets = vec![Widget::DecoratedText {
+        top_label: Some(failed.source.display().to_string()),
+        text: name,
+        bottom_label: (!reason.is_empty()).then_some(reason),
+        wrap_text: true,
+    }];
+    let stack_trace = failed.stack_trace(stack_trace_lines);
+    if !stack_trace.is_empty() {
+        const STACK_TRACE_FONT: &str = "<font color=\"#666666\"></font>";
+        let max_stack_trace = MAX_WIDGET_TEXT_LENGTH - STACK_TRACE_FONT.len();
+        widgets.push(Widget::paragraph(format!(
+            "<font color=\"#666666\">{}</font>",
+            escape_html_within(&stack_trace.join("\n"), max_stack_trace)
+        )));
+    }
+    widgets
+}
+
+/// Escapes `text` for HTML, cut so that the result has at most `max_chars`
+/// characters. The cut falls between characters of `text`, never inside an
+/// entity, and is marked with "…".
+fn escape_html_within(text: &str, max_chars: usize) -> String {
+    let escaped = escape_html(text);
+    if escaped.chars().count() <= max_chars {
+        return escaped;
+    }
+    let mut cut = String::new();
+    let mut length = 0;
+    for c in text.chars() {
+        let escaped = escape_html(c.encode_utf8(&mut [0; 4]));
+        length += escaped.chars().count();
+        if length + 1 > max_chars {
+            break;
+        }
+        cut.push_str(&escaped);
+    }
+    cut.push('…');
+    cut
+}
+
+/// The entry with its note linked to the item's URL, if it has one.
+fn format_item(item: &ListItem) -> String {
+    let name = escape_html(item.name);
+    match (&item.note, item.link) {
+        (Some(note), Some(url)) => format!(
+            "{} (<a href=\"{}\">{}</a>)",
+            name,
+            escape_html(url),
+            escape_html(note)
+        ),
+        (Some(note), None) => format!("{} ({})", name, escape_html(note)),
+        _ => name,
+    }
+}
+
+/// A collapsed section with one line per entry, as many as fit in a widget.
+/// The rest are counted in a last line.
+fn list_section(list: &ListSection) -> Section {
+    let more = |remaining: usize| format!("\n…and {} more", remaining);
+    let mut text = String::new();
+    let mut length = 0;
+    for (index, item) in list.items.iter().enumerate() {
+        let line = format!(
+            "{}• {}",
+            if index == 0 { "" } else { "\n" },
+            format_item(item)
+        );
+        let remaining = list.items.len() - index - 1;
+        let reserved = if remaining > 0 {
+            more(remaining).chars().count()
+        } else {
+            0
+        };
+        length += line.chars().count();
+        if length + reserved > MAX_WIDGET_TEXT_LENGTH {
+            text.push_str(more(list.items.len() - index).trim_start());
+            break;
+        }
+        text.push_str(&line);
+    }
+    Section {
+        header: Some(list.heading()),
+        collapsible: true,
+        uncollapsible_widgets_count: Some(0),
+        widgets: vec![Widget::paragraph(text)],
+    }
+}
+
+/// Plain text for notifications.
+fn fallback_text(run: &TestRun, args: &MessageArgs) -> String {
+    format!("{}: {}", args.title, run.summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{message_args, test_run};
+    use crate::history::FailureStatus;
+    use crate::http::tests::test_client;
+    use junit_parser::TestStatus;
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    #[test]
+    fn test_build_card() {
+        let mut run = test_run(1);
+        run.failed_tests[0].case.name = "test<T>".to_string();
+        run.failed_tests[0].history = Some(FailureStatus::New);
+        let card = serde_json::to_value(build_card(&run, &message_args(20))).unwrap();
+        assert_eq!(
+            card["header"],
+            json!({"title": "Nightly", "subtitle": "1 failed"})
+        );
+        assert_eq!(
+            card["sections"][0]["widgets"][0]["decoratedText"],
+            json!({
+                "topLabel": "2 tests",
+                "text": "<b>1</b> passed, <b>1</b> failed, <b>0</b> errored, <b>0</b> skipped",
+                "bottomLabel": "50.0% pass rate in 1.0s",
+                "wrapText": true,
+            })
+        );
+        let failures = &card["sections"][1];
+        assert_eq!(failures["header"], "Failed tests (1)");
+        assert_eq!(failures["collapsible"], true);
+        assert_eq!(
+            failures["widgets"][0]["decoratedText"]["text"],
+            "<b>test&lt;T&gt;</b> <font color=\"#E01E5A\">NEW</font>"
+        );
+        assert_eq!(
+            failures["widgets"][0]["decoratedText"]["bottomLabel"],
+            "AssertionError: expected &lt;1&gt; but was &lt;2&gt;"
+        );
+        assert_eq!(
+            failures["widgets"][1]["textParagraph"]["text"],
+            "<font color=\"#666666\">AssertionError\n\tat test_0</font>"
+        );
+    }
+
+    #[test]
+    fn test_build_card_cuts_long_texts_outside_markup() {
+        let mut run = test_run(1);
+        if let TestStatus::Failure(failure) = &mut run.failed_tests[0].case.status {
+            failure.text = "at ```List<T>``` & more\n".repeat(200);
+        }
+        run.fixed_tests = (0..500).map(|index| format!("test<{}>", index)).collect();
+        let args = MessageArgs {
+            stack_trace_lines: 200,
+            ..message_args(20)
+        };
+
+        let card = serde_json::to_value(build_card(&run, &args)).unwrap();
+        let stack_trace = card["sections"][1]["widgets"][1]["textParagraph"]["text"]
+            .as_str()
+            .unwrap();
+        assert!(stack_trace.chars().count() <= MAX_WIDGET_TEXT_LENGTH);
+        assert!(stack_trace.starts_with("<font color=\"#666666\">at ```List&lt;T&gt;```"));
+        assert!(stack_trace.ends_with("…</font>"));
+        assert!(!stack_trace.contains("&…") && !stack_trace.contains("&l…"));
+
+        let fixed = card["sections"][2]["widgets"][0]["textParagraph"]["text"]
+            .as_str()
+            .unwrap();
+        assert!(fixed.chars().count() <= MAX_WIDGET_TEXT_LENGTH);
+        assert!(fixed.starts_with("• test&lt;0&gt;\n"));
+        assert!(fixed.ends_with(" more"));
+    }
+
+    #[test]
+    fn test_google_chat_notifier_posts_in_thread() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/v1/spaces/AAA/messages")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("key".into(), "k".into()),
+                Matcher::UrlEncoded("threadKey".into(), "nightly-main".into()),
+                Matcher::UrlEncoded(
+                    "messageReplyOption".into(),
+                    "REPLY_MESSAGE_FALLBACK_TO_NEW_THREAD".into(),
+                ),
+            ]))
+            .match_body(Matcher::PartialJson(json!({
+                "cardsV2": [{"cardId": "test-results", "card": {"header": {"title": "Nightly"}}}],
+            })))
+            .create();
+        let client = test_client();
+        let url = format!("{}/v1/spaces/AAA/messages?key=k", server.url())
+            .parse()
+            .unwrap();
+
+        GoogleChatNotifier::new(url, Some("nightly-main"), &client)
+            .notify(&test_run(1), &message_args(20))
+            .unwrap();
+        mock.assert();
+    }
+}
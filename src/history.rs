@@ -0,0 +1,283 @@
+use crate::cli::HistoryArgs;
+use crate::error::{Error, Result};
+use crate::TestRun;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One line of the history file: the tests that failed in a single run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub project: String,
+    pub branch: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub failures: Vec<String>,
+}
+
+/// How a failure relates to the previous runs of the same project and branch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureStatus {
+    New,
+    /// Failing in this run and the `streak - 1` runs before it.
+    StillFailing {
+        streak: usize,
+    },
+}
+
+impl fmt::Display for FailureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureStatus::New => write!(f, "NEW"),
+            FailureStatus::StillFailing { streak } => {
+                write!(f, "STILL FAILING ({} runs)", streak)
+            }
+        }
+    }
+}
+
+/// Previous runs of one project and branch, read from a JSON-lines file.
+///
+/// Runs of other projects and branches may share the file; they are kept
+/// as-is when new runs are appended.
+pub struct History {
+    path: PathBuf,
+    project: String,
+    branch: String,
+    /// Earlier runs of this project and branch, oldest first.
+    runs: Vec<RunRecord>,
+}
+
+impl History {
+    /// Reads the history file, or returns `None` when none is configured.
+    ///
+    /// A missing file is an empty history. Lines that cannot be parsed, such
+    /// as one cut short by an interrupted write, are skipped with a warning.
+    pub fn open(args: &HistoryArgs) -> Result<Option<Self>> {
+        let Some(path) = &args.history_file else {
+            return Ok(None);
+        };
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(Error::config_file(path, err)),
+        };
+
+        let mut runs = vec![];
+        for (index, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<RunRecord>(line) {
+                Ok(run) if run.project == args.project && run.branch == args.branch => {
+                    runs.push(run)
+                }
+                Ok(_) => {}
+                Err(err) => eprintln!(
+                    "Skipping invalid line {} of {}: {}",
+                    index + 1,
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        Ok(Some(History {
+            path: path.clone(),
+            project: args.project.clone(),
+            branch: args.branch.clone(),
+            runs,
+        }))
+    }
+
+    /// The most recent earlier run, if any.
+    pub fn last_run(&self) -> Option<&RunRecord> {
+        self.runs.last()
+    }
+
+    /// Whether the previous run had failures and `run` has none.
+    pub fn recovered(&self, run: &TestRun) -> bool {
+        run.failed_tests.is_empty()
+            && self
+                .last_run()
+                .is_some_and(|last| !last.failures.is_empty())
+    }
+
+    /// Marks every failure in `run` as new or still failing, and lists the
+    /// tests that failed last time and pass now.
+    pub fn classify(&self, run: &mut TestRun) {
+        let previous: Vec<HashSet<&str>> = self
+            .runs
+            .iter()
+            .rev()
+            .map(|record| record.failures.iter().map(String::as_str).collect())
+            .collect();
+        for failed in &mut run.failed_tests {
+            let name = failed.case.name.as_str();
+            let earlier = previous
+                .iter()
+                .take_while(|failures| failures.contains(name))
+                .count();
+            failed.history = Some(if earlier == 0 {
+                FailureStatus::New
+            } else {
+                FailureStatus::StillFailing {
+                    streak: earlier + 1,
+                }
+            });
+        }
+
+        run.fixed_tests = self
+            .last_run()
+            .map(|last| {
+                last.failures
+                    .iter()
+                    .filter(|name| run.passed_tests.contains(*name))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+
+    /// Appends the failures of `run` to the history file.
+    pub fn record(&self, run: &TestRun) -> Result<()> {
+        let record = RunRecord {
+            project: self.project.clone(),
+            branch: self.branch.clone(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            failures: run
+                .failed_tests
+                .iter()
+                .map(|failed| failed.case.name.clone())
+                .collect(),
+        };
+        let line = serde_json::to_string(&record).expect("run records serialize to JSON");
+
+        if let Some(parent) = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| Error::io(&self.path, err))?;
+        writeln!(file, "{}", line).map_err(|err| Error::io(&self.path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::path::Path;
+
+    fn history_args(path: &Path, branch: &str) -> HistoryArgs {
+        HistoryArgs {
+            history_file: Some(path.to_path_buf()),
+            project: "app".to_string(),
+            branch: branch.to_string(),
+        }
+    }
+
+    fn test_run(failed: &[&str], passed: &[&str]) -> TestRun {
+        TestRun {
+            failed_tests: failed
+                .iter()
+                .map(|name| fixtures::failed_test(name))
+                .collect(),
+            passed_tests: passed.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_classify_failures_against_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history/runs.jsonl");
+        let main = history_args(&path, "main");
+
+        History::open(&main)
+            .unwrap()
+            .unwrap()
+            .record(&test_run(&["a", "b"], &[]))
+            .unwrap();
+        History::open(&main)
+            .unwrap()
+            .unwrap()
+            .record(&test_run(&["a", "c"], &["b"]))
+            .unwrap();
+        History::open(&history_args(&path, "feature"))
+            .unwrap()
+            .unwrap()
+            .record(&test_run(&["d"], &[]))
+            .unwrap();
+
+        let mut run = test_run(&["a", "d"], &["b", "c"]);
+        History::open(&main).unwrap().unwrap().classify(&mut run);
+
+        let statuses: Vec<_> = run
+            .failed_tests
+            .iter()
+            .map(|failed| failed.history)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                Some(FailureStatus::StillFailing { streak: 3 }),
+                Some(FailureStatus::New),
+            ]
+        );
+        assert_eq!(run.fixed_tests, vec!["c"]);
+    }
+
+    #[test]
+    fn test_recovered_only_after_failing_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = history_args(&dir.path().join("runs.jsonl"), "main");
+        let green = test_run(&[], &["a"]);
+
+        assert!(!History::open(&args).unwrap().unwrap().recovered(&green));
+        History::open(&args)
+            .unwrap()
+            .unwrap()
+            .record(&test_run(&["a"], &[]))
+            .unwrap();
+        let history = History::open(&args).unwrap().unwrap();
+        assert!(history.recovered(&green));
+        assert!(!history.recovered(&test_run(&["a"], &[])));
+
+        history.record(&green).unwrap();
+        assert!(!History::open(&args).unwrap().unwrap().recovered(&green));
+    }
+
+    #[test]
+    fn test_open_skips_invalid_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runs.jsonl");
+        fs::write(
+            &path,
+            "{\"project\":\"app\",\"branch\":\"main\",\"timestamp\":1,\"failures\":[\"a\"]}\n{\"proj",
+        )
+        .unwrap();
+
+        let history = History::open(&history_args(&path, "main"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(history.last_run().unwrap().failures, vec!["a"]);
+
+        let missing = dir.path().join("missing.jsonl");
+        let history = History::open(&history_args(&missing, "main"))
+            .unwrap()
+            .unwrap();
+        assert!(history.last_run().is_none());
+    }
+}
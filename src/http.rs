@@ -0,0 +1,254 @@
+use crate::cli::DeliveryArgs;
+use crate::error::{Error, Result};
+use crate::retry::{Attempt, RetryPolicy};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Blocking HTTP client that retries transient delivery failures.
+///
+/// Connection errors, timeouts, `429 Too Many Requests` and `5xx` responses
+/// are retried following the [`RetryPolicy`], or after the delay the server
+/// asked for in `Retry-After` or in a JSON body's `retry_after`. Other
+/// responses are returned as-is or turned into an error straight away.
+pub struct HttpClient {
+    client: Client,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+}
+
+impl HttpClient {
+    pub fn new(args: &DeliveryArgs) -> Result<Self> {
+        let timeout = Duration::from_secs(args.timeout_secs);
+        let client = Client::builder()
+            .connect_timeout(timeout)
+            .timeout(timeout)
+            .build()
+            .map_err(|err| Error::request_failed("Failed to create HTTP client", err))?;
+        Ok(HttpClient {
+            client,
+            timeout,
+            retry_policy: RetryPolicy::new(args),
+        })
+    }
+
+    /// Time allowed for each request as a whole, from connecting to reading
+    /// the response. Other transports apply it to each network operation.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The retries that other transports than HTTP follow too.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Posts `body` as JSON to `url` and returns the first successful response.
+    ///
+    /// `service` names the receiving end in error messages.
+    pub fn post_json<T: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        body: &T,
+        service: &str,
+    ) -> Result<Response> {
+        self.send(|| self.client.post(url).json(body), service)
+    }
+
+    /// Like [`HttpClient::post_json`], authenticating with a bearer token.
+    pub fn post_json_with_token<T: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        token: &str,
+        body: &T,
+        service: &str,
+    ) -> Result<Response> {
+        self.send(
+            || self.client.post(url).bearer_auth(token).json(body),
+            service,
+        )
+    }
+
+    fn send(&self, request: impl Fn() -> RequestBuilder, service: &str) -> Result<Response> {
+        self.retry_policy.retry(|| match request().send() {
+            Ok(response) if response.status().is_success() => Attempt::Done(response),
+            Ok(response) if is_retryable(response.status()) => {
+                let status = response.status();
+                let header_delay = retry_after(&response);
+                let body = response.text().unwrap_or_default();
+                let delay = retry_after_in_body(&body).or(header_delay);
+                Attempt::Retry(api_error(status, &body, service), delay)
+            }
+            Ok(response) => Attempt::Fail(status_error(response, service)),
+            Err(err) => {
+                let retryable = err.is_timeout() || err.is_connect();
+                let error =
+                    Error::request_failed(format!("Failed to send message to {}", service), err);
+                if retryable {
+                    Attempt::Retry(error, None)
+                } else {
+                    Attempt::Fail(error)
+                }
+            }
+        })
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Reads the delay from a `Retry-After` header given in seconds, as Slack does.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Body of a rate-limited response from Discord, which gives the delay in
+/// seconds with a fraction, more precisely than its `Retry-After` header.
+#[derive(Deserialize)]
+struct RateLimited {
+    retry_after: f64,
+}
+
+fn retry_after_in_body(body: &str) -> Option<Duration> {
+    let limited: RateLimited = serde_json::from_str(body).ok()?;
+    Duration::try_from_secs_f64(limited.retry_after).ok()
+}
+
+fn status_error(response: Response, service: &str) -> Error {
+    let status = response.status();
+    let error_text = response.text().unwrap_or_default();
+    api_error(status, &error_text, service)
+}
+
+fn api_error(status: StatusCode, error_text: &str, service: &str) -> Error {
+    Error::delivery(format!(
+        "{} API error ({}): {}",
+        service, status, error_text
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use mockito::Server;
+    use std::time::Instant;
+
+    pub(crate) fn test_client() -> HttpClient {
+        HttpClient::new(&DeliveryArgs {
+            retries: 2,
+            retry_delay_ms: 1,
+            deadline_secs: 5,
+            timeout_secs: 5,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_post_json_retries_server_errors() {
+        let mut server = Server::new();
+        let failure = server.mock("POST", "/").with_status(503).expect(1).create();
+        let success = server.mock("POST", "/").with_status(200).create();
+
+        test_client()
+            .post_json(&server.url(), "payload", "Slack")
+            .unwrap();
+        failure.assert();
+        success.assert();
+    }
+
+    #[test]
+    fn test_post_json_gives_up_after_retries() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .with_status(500)
+            .with_body("internal_error")
+            .expect(3)
+            .create();
+
+        let err = test_client()
+            .post_json(&server.url(), "payload", "Slack")
+            .unwrap_err();
+        mock.assert();
+        assert_eq!(
+            err.to_string(),
+            "Slack API error (500 Internal Server Error): internal_error"
+        );
+    }
+
+    #[test]
+    fn test_post_json_does_not_retry_client_errors() {
+        let mut server = Server::new();
+        let mock = server.mock("POST", "/").with_status(404).expect(1).create();
+
+        assert!(test_client()
+            .post_json(&server.url(), "payload", "Slack")
+            .is_err());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_post_json_waits_for_retry_after() {
+        let mut server = Server::new();
+        let limited = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "1")
+            .expect(1)
+            .create();
+        let success = server.mock("POST", "/").with_status(200).create();
+
+        let started = Instant::now();
+        test_client()
+            .post_json(&server.url(), "payload", "Slack")
+            .unwrap();
+        limited.assert();
+        success.assert();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_post_json_waits_for_retry_after_in_body() {
+        let mut server = Server::new();
+        let limited = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "30")
+            .with_body(r#"{"message": "You are being rate limited.", "retry_after": 0.5, "global": false}"#)
+            .expect(1)
+            .create();
+        let success = server.mock("POST", "/").with_status(204).create();
+
+        let started = Instant::now();
+        test_client()
+            .post_json(&server.url(), "payload", "Discord")
+            .unwrap();
+        limited.assert();
+        success.assert();
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_post_json_respects_retry_after_and_deadline() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "30")
+            .expect(1)
+            .create();
+
+        let started = Instant::now();
+        let err = test_client()
+            .post_json(&server.url(), "payload", "Slack")
+            .unwrap_err();
+        mock.assert();
+        assert!(err.to_string().contains("429"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
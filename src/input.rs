@@ -0,0 +1,149 @@
+use crate::error::{Error, Result};
+use crate::flaky::{self, Rerun, Reruns};
+use junit_parser::{TestCase, TestSuite};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Test suites parsed from a single JUnit XML file.
+pub struct ReportFile {
+    pub path: PathBuf,
+    pub suites: Vec<TestSuite>,
+    pub reruns: Reruns,
+}
+
+impl ReportFile {
+    /// Failed attempts recorded for `case` before its final result.
+    pub fn reruns(&self, case: &TestCase) -> &[Rerun] {
+        self.reruns
+            .get(&flaky::rerun_key(case))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Resolves the given inputs into a sorted, de-duplicated list of report files.
+///
+/// Each input may be a file, a directory (searched recursively for `*.xml`)
+/// or a glob pattern such as `**/target/surefire-reports/TEST-*.xml`.
+pub fn resolve_inputs(inputs: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = BTreeSet::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            collect_xml_files(path, &mut files)?;
+        } else if path.is_file() {
+            files.insert(path.to_path_buf());
+        } else if is_glob_pattern(input) {
+            let entries = glob::glob(input)
+                .map_err(|err| Error::Config(format!("Invalid glob pattern {}: {}", input, err)))?;
+            let before = files.len();
+            for entry in entries {
+                let entry = entry.map_err(|err| {
+                    let path = err.path().to_path_buf();
+                    Error::io(path, err.into())
+                })?;
+                if entry.is_dir() {
+                    collect_xml_files(&entry, &mut files)?;
+                } else {
+                    files.insert(entry);
+                }
+            }
+            if files.len() == before {
+                return Err(Error::not_found(input));
+            }
+        } else {
+            return Err(Error::not_found(input));
+        }
+    }
+    Ok(files.into_iter().collect())
+}
+
+/// Reads and parses every report file.
+pub fn load_reports(paths: &[PathBuf]) -> Result<Vec<ReportFile>> {
+    paths
+        .iter()
+        .map(|path| {
+            let xml_content = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+            let parse_error = |source| Error::Parse {
+                path: path.clone(),
+                source,
+            };
+            let suites = junit_parser::from_reader(xml_content.as_bytes()).map_err(parse_error)?;
+            let reruns = flaky::parse_reruns(&xml_content).map_err(parse_error)?;
+            Ok(ReportFile {
+                path: path.clone(),
+                suites: suites.suites,
+                reruns,
+            })
+        })
+        .collect()
+}
+
+fn collect_xml_files(dir: &Path, files: &mut BTreeSet<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).map_err(|err| Error::io(dir, err))?;
+    for entry in entries {
+        let path = entry.map_err(|err| Error::io(dir, err))?.path();
+        if path.is_dir() {
+            collect_xml_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "xml") {
+            files.insert(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_glob_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = r#"<testsuite name="suite" tests="1"><testcase name="a"/></testsuite>"#;
+
+    #[test]
+    fn test_resolve_inputs_walks_directories_and_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let module = dir.path().join("module/target");
+        fs::create_dir_all(&module).unwrap();
+        fs::write(module.join("TEST-a.xml"), REPORT).unwrap();
+        fs::write(module.join("TEST-b.xml"), REPORT).unwrap();
+        fs::write(module.join("notes.txt"), "").unwrap();
+
+        let from_dir = resolve_inputs(&[dir.path().display().to_string()]).unwrap();
+        assert_eq!(from_dir.len(), 2);
+
+        let pattern = format!("{}/**/TEST-a.xml", dir.path().display());
+        let from_glob =
+            resolve_inputs(&[pattern, module.join("TEST-a.xml").display().to_string()]).unwrap();
+        assert_eq!(from_glob, vec![module.join("TEST-a.xml")]);
+    }
+
+    #[test]
+    fn test_resolve_inputs_rejects_missing_files() {
+        assert!(matches!(
+            resolve_inputs(&["does-not-exist.xml".to_string()]),
+            Err(Error::Io { .. })
+        ));
+        assert!(matches!(
+            resolve_inputs(&["does-not-exist/*.xml".to_string()]),
+            Err(Error::Io { .. })
+        ));
+        assert!(matches!(
+            resolve_inputs(&["[".to_string()]),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_reports_reports_parse_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.xml");
+        fs::write(&path, "<testsuite><testcase name=\"a\">").unwrap();
+
+        let err = load_reports(&[path]).err().unwrap();
+        assert!(matches!(err, Error::Parse { .. }));
+        assert_eq!(err.exit_code(), std::process::ExitCode::from(4));
+    }
+}
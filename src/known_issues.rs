@@ -0,0 +1,156 @@
+use crate::error::{Error, Result};
+use crate::FailedTest;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Layout of the known issues file:
+///
+/// ```toml
+/// [[issue]]
+/// label = "INFRA-123"
+/// url = "https://jira.example.com/browse/INFRA-123"
+/// pattern = "Connection refused: redis"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KnownIssuesFile {
+    #[serde(default)]
+    issue: Vec<RawRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    label: String,
+    url: Option<String>,
+    pattern: String,
+}
+
+/// A tracked root cause that failures can be attributed to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnownIssue {
+    pub label: String,
+    pub url: Option<String>,
+}
+
+struct Rule {
+    issue: KnownIssue,
+    pattern: Regex,
+}
+
+/// Rules attributing failures to known issues by their type, message or
+/// stack trace.
+#[derive(Default)]
+pub struct KnownIssues {
+    rules: Vec<Rule>,
+}
+
+impl KnownIssues {
+    /// Reads the rules file, or returns no rules without one.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(KnownIssues::default());
+        };
+        let content = fs::read_to_string(path).map_err(|err| Error::config_file(path, err))?;
+        KnownIssues::parse(&content).map_err(|err| {
+            Error::Config(format!(
+                "Invalid known issues file {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    fn parse(content: &str) -> std::result::Result<Self, String> {
+        let file: KnownIssuesFile = toml::from_str(content).map_err(|err| err.to_string())?;
+        let rules = file
+            .issue
+            .into_iter()
+            .map(|raw| {
+                let pattern = Regex::new(&raw.pattern)
+                    .map_err(|err| format!("invalid pattern for {}: {}", raw.label, err))?;
+                Ok(Rule {
+                    issue: KnownIssue {
+                        label: raw.label,
+                        url: raw.url,
+                    },
+                    pattern,
+                })
+            })
+            .collect::<std::result::Result<_, String>>()?;
+        Ok(KnownIssues { rules })
+    }
+
+    /// The issue of the first rule matching the failure's type, message or
+    /// stack trace.
+    pub fn find(&self, failed: &FailedTest) -> Option<&KnownIssue> {
+        let details = failed.details()?;
+        self.rules
+            .iter()
+            .find(|rule| {
+                [details.failure_type, details.message, details.text]
+                    .iter()
+                    .any(|text| rule.pattern.is_match(text))
+            })
+            .map(|rule| &rule.issue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use junit_parser::{TestError, TestStatus};
+
+    fn failed_test(error_type: &str, text: &str) -> FailedTest {
+        let mut failed = fixtures::failed_test("test");
+        failed.case.status = TestStatus::Error(TestError {
+            error_type: error_type.to_string(),
+            text: text.to_string(),
+            ..Default::default()
+        });
+        failed
+    }
+
+    #[test]
+    fn test_find_matches_type_message_or_stack_trace() {
+        let known_issues = KnownIssues::parse(
+            r#"
+            [[issue]]
+            label = "INFRA-123"
+            url = "https://jira.example.com/browse/INFRA-123"
+            pattern = "Connection refused: redis"
+
+            [[issue]]
+            label = "Timeouts"
+            pattern = "^java\\.util\\.concurrent\\.TimeoutException$"
+            "#,
+        )
+        .unwrap();
+
+        let redis = failed_test(
+            "java.io.IOException",
+            "java.io.IOException: Connection refused: redis:6379\n\tat Client.connect",
+        );
+        assert_eq!(
+            known_issues.find(&redis),
+            Some(&KnownIssue {
+                label: "INFRA-123".to_string(),
+                url: Some("https://jira.example.com/browse/INFRA-123".to_string()),
+            })
+        );
+        let timeout = failed_test("java.util.concurrent.TimeoutException", "");
+        assert_eq!(known_issues.find(&timeout).unwrap().label, "Timeouts");
+        assert!(known_issues
+            .find(&failed_test("AssertionError", "expected <1>"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_patterns() {
+        assert!(KnownIssues::parse("[[issue]]\nlabel = \"A\"\npattern = \"(\"").is_err());
+        assert!(KnownIssues::parse("[[issue]]\nlabel = \"A\"").is_err());
+    }
+}
@@ -1,92 +1,576 @@
-use anyhow::Context;
+mod cli;
+mod cluster;
+mod date;
+mod diff;
+mod discord;
+mod email;
+mod error;
+mod flaky;
+mod google_chat;
+mod history;
+mod http;
+mod input;
+mod known_issues;
+mod markdown;
+mod mentions;
+mod notifier;
+mod ownership;
+mod quarantine;
+mod retry;
+mod slack;
+mod summary;
+mod teams;
+
+use clap::Parser;
+use cli::{
+    Cli, Command, DiffArgs, GroupBy, MessageArgs, OutputFormat, ReportArgs, SendArgs,
+    SummarizeArgs, TriageArgs,
+};
+use diff::Comparison;
+use error::Result;
+use flaky::Rerun;
+use history::{FailureStatus, History};
+use http::HttpClient;
+use input::ReportFile;
 use junit_parser::{TestCase, TestStatus, TestSuite};
-use serde::Serialize;
-use std::{env, fs};
+use known_issues::{KnownIssue, KnownIssues};
+use mentions::MentionRules;
+use notifier::{Notifier, SlackNotifier};
+use ownership::{CodeOwners, Owners};
+use quarantine::{Quarantine, QuarantineEntry};
+use slack::Destination;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use summary::Summary;
 
-#[derive(Serialize)]
-struct SlackMessage {
-    text: String,
+/// A failed test case together with the report file it was read from.
+#[derive(Clone)]
+struct FailedTest {
+    case: TestCase,
+    source: PathBuf,
+    /// Names of the enclosing test suites, outermost first.
+    suite_path: Vec<String>,
+    /// How this failure relates to earlier runs, when a history file is used.
+    history: Option<FailureStatus>,
+    /// The known issue this failure was attributed to, if any.
+    known_issue: Option<KnownIssue>,
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let file_path = args.get(1).map(|s| s.as_str()).unwrap_or("junit.xml");
-    let webhook_url = env::var("SLACK_WEBHOOK_URL")
-        .context("SLACK_WEBHOOK_URL environment variable not set")
-        .unwrap();
+/// The `<failure>` or `<error>` element reported for a failed test case.
+struct FailureDetails<'a> {
+    kind: &'static str,
+    failure_type: &'a str,
+    message: &'a str,
+    text: &'a str,
+}
 
-    let xml_content = fs::read_to_string(file_path)
-        .with_context(|| format!("Failed to read file {}", file_path))
-        .unwrap();
-    let suites = junit_parser::from_reader(xml_content.as_bytes()).unwrap();
+impl FailedTest {
+    /// The group this failure is listed under, or `None` when not grouping.
+    fn group(&self, group_by: GroupBy) -> Option<String> {
+        let classname = self.case.classname.as_deref().unwrap_or_default();
+        let group = match group_by {
+            GroupBy::None => return None,
+            GroupBy::Suite => self.suite_path.first().cloned().unwrap_or_default(),
+            GroupBy::SuitePath => self.suite_path.join(" › "),
+            GroupBy::Package => classname
+                .rsplit_once('.')
+                .map_or(String::new(), |(package, _)| package.to_string()),
+            GroupBy::Classname => classname.to_string(),
+        };
+        Some(if group.is_empty() {
+            "(none)".to_string()
+        } else {
+            group
+        })
+    }
 
-    let mut failed_tests = vec![];
-    collect_failed_tests(&suites.suites, &mut failed_tests);
-    if failed_tests.is_empty() {
-        println!("All tests passed successfully!");
-    } else {
-        let message = format_slack_message(&failed_tests);
-        send_slack_message(&message, &webhook_url);
+    fn details(&self) -> Option<FailureDetails<'_>> {
+        match &self.case.status {
+            TestStatus::Failure(failure) => Some(FailureDetails {
+                kind: "Failure",
+                failure_type: &failure.failure_type,
+                message: &failure.message,
+                text: &failure.text,
+            }),
+            TestStatus::Error(error) => Some(FailureDetails {
+                kind: "Error",
+                failure_type: &error.error_type,
+                message: &error.message,
+                text: &error.text,
+            }),
+            TestStatus::Success | TestStatus::Skipped(_) => None,
+        }
+    }
+
+    /// Why the test failed, as `type: message` in plain text.
+    fn reason(&self) -> String {
+        let Some(details) = self.details() else {
+            return String::new();
+        };
+        let failure_type = details.failure_type.trim();
+        let message = details.message.trim();
+        match (failure_type.is_empty(), message.is_empty()) {
+            (false, false) => format!("{}: {}", failure_type, message),
+            (false, true) => failure_type.to_string(),
+            (true, false) => message.to_string(),
+            (true, true) => details.kind.to_string(),
+        }
+    }
+
+    /// The first `lines` non-blank lines of the stack trace.
+    fn stack_trace(&self, lines: usize) -> Vec<&str> {
+        self.details().map_or(vec![], |details| {
+            details
+                .text
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.trim().is_empty())
+                .take(lines)
+                .collect()
+        })
     }
 }
 
-fn collect_failed_tests(test_suites: &[TestSuite], result: &mut Vec<TestCase>) {
-    for suite in test_suites {
-        collect_failed_tests(&suite.suites, result);
-        for case in &suite.cases {
-            if has_failures(case) {
-                result.push(case.clone());
+/// Failures, runs and message options shared by the tests of the notifiers.
+#[cfg(test)]
+mod fixtures {
+    use super::*;
+    use crate::cli::{Layout, MessageArgs, Overflow};
+    use junit_parser::TestFailure;
+
+    /// The default options, titled "Nightly" with two stack trace lines.
+    pub fn message_args(max_failures: usize) -> MessageArgs {
+        MessageArgs {
+            title: "Nightly".to_string(),
+            stack_trace_lines: 2,
+            max_failures,
+            overflow: Overflow::Truncate,
+            threaded: false,
+            layout: Layout::List,
+            group_by: GroupBy::None,
+        }
+    }
+
+    /// A failed assertion in `name`, read from `junit.xml`.
+    pub fn failed_test(name: &str) -> FailedTest {
+        FailedTest {
+            case: TestCase {
+                name: name.to_string(),
+                status: TestStatus::Failure(TestFailure {
+                    message: "expected <1> but was <2>".to_string(),
+                    text: format!("AssertionError\n\tat {}\n\tat run", name),
+                    failure_type: "AssertionError".to_string(),
+                }),
+                ..Default::default()
+            },
+            source: PathBuf::from("junit.xml"),
+            suite_path: vec![],
+            history: None,
+            known_issue: None,
+        }
+    }
+
+    /// A failure of `name` muted by a quarantine entry with ticket `PAY-12`.
+    pub fn quarantined_test(name: &str) -> QuarantinedTest {
+        QuarantinedTest {
+            failed: failed_test(name),
+            entry: QuarantineEntry {
+                classname: glob::Pattern::new("*").unwrap(),
+                name: glob::Pattern::new("*").unwrap(),
+                ticket: Some("PAY-12".to_string()),
+                ticket_url: Some("https://jira.example.com/browse/PAY-12".to_string()),
+                expires: None,
+            },
+        }
+    }
+
+    /// A run where `test_0`, `test_1`… failed and one other test passed.
+    pub fn test_run(failures: usize) -> TestRun {
+        TestRun {
+            failed_tests: (0..failures)
+                .map(|index| failed_test(&format!("test_{}", index)))
+                .collect(),
+            summary: Summary {
+                total: failures + 1,
+                passed: 1,
+                failed: failures,
+                duration: 1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Like [`test_run`], with `extra_len` more characters in each stack
+    /// trace to reach the size limits of the services.
+    pub fn test_run_with_stack_trace(failures: usize, extra_len: usize) -> TestRun {
+        let mut run = test_run(failures);
+        for failed in &mut run.failed_tests {
+            if let TestStatus::Failure(failure) = &mut failed.case.status {
+                failure.text.push_str(&"x".repeat(extra_len));
             }
         }
+        run
     }
 }
 
-fn has_failures(case: &TestCase) -> bool {
-    match case.status {
-        TestStatus::Success => false,
-        TestStatus::Error(_) => true,
-        TestStatus::Failure(_) => true,
-        TestStatus::Skipped(_) => false,
+/// A test that failed at first but passed when it was rerun.
+#[derive(Clone)]
+struct FlakyTest {
+    case: TestCase,
+    /// Number of runs, including the final passing one.
+    attempts: usize,
+    first_failure: Rerun,
+}
+
+/// The quarantine and known issue files, applied to every failure.
+struct Triage {
+    quarantine: Quarantine,
+    known_issues: KnownIssues,
+}
+
+impl Triage {
+    fn load(args: &TriageArgs) -> Result<Self> {
+        Ok(Triage {
+            quarantine: Quarantine::load(args.quarantine_file.as_deref())?,
+            known_issues: KnownIssues::load(args.known_issues_file.as_deref())?,
+        })
     }
 }
 
-fn format_slack_message(failed_cases: &[TestCase]) -> String {
-    let title = env::var("SLACK_MESSAGE_TITLE").unwrap_or_else(|_| "Test Results".to_string());
-    let mut message = format!("*{}*\n\n", title);
-    for case in failed_cases {
-        append_case_info(&mut message, case);
+/// A failure muted by an entry of the quarantine file.
+#[derive(Clone)]
+struct QuarantinedTest {
+    failed: FailedTest,
+    entry: QuarantineEntry,
+}
+
+/// Everything collected from the input reports.
+#[derive(Clone, Default)]
+struct TestRun {
+    failed_tests: Vec<FailedTest>,
+    /// Failures of quarantined tests, reported without alerting anyone.
+    quarantined_tests: Vec<QuarantinedTest>,
+    /// Quarantine entries past their expiry date.
+    expired_quarantine: Vec<QuarantineEntry>,
+    flaky_tests: Vec<FlakyTest>,
+    /// Names of the tests that passed, including flaky ones.
+    passed_tests: HashSet<String>,
+    /// Tests that failed in the previous run and pass now.
+    fixed_tests: Vec<String>,
+    /// Users and user groups to notify, in Slack's mention syntax.
+    mentions: Vec<String>,
+    summary: Summary,
+}
+
+fn main() -> ExitCode {
+    let command = Cli::parse().into_command().unwrap_or_else(|err| err.exit());
+    let result = match command {
+        Command::Send(args) => send(&args),
+        Command::Summarize(args) => summarize(&args),
+        Command::Validate(args) => validate(&args),
+        Command::Diff(args) => diff(&args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let exit_code = err.exit_code();
+            eprintln!("Error: {:#}", anyhow::Error::from(err));
+            exit_code
+        }
     }
-    message
 }
 
-fn append_case_info(message: &mut String, case: &TestCase) {
-    message.push_str(&format!("- {}\n", case.name.to_string()));
+fn send(args: &SendArgs) -> Result<()> {
+    let mut run = load_test_run(&args.report, &args.triage)?;
+    let history = History::open(&args.history)?;
+    if let Some(history) = &history {
+        history.classify(&mut run);
+    }
+    let mention_rules = MentionRules::load(&args.mentions, history.is_some())?;
+    run.mentions = mention_rules.mentions(&run, &args.history.branch);
+    println!("{}", run.summary);
+    let recovered = history
+        .as_ref()
+        .is_some_and(|history| history.recovered(&run));
+    let notify_on_flaky = args.notify_on_flaky && !run.flaky_tests.is_empty();
+    let notify_on_recovery = args.notify_on_recovery && recovered;
+    let delivered = if run.failed_tests.is_empty()
+        && !args.notify_on_success
+        && !notify_on_flaky
+        && !notify_on_recovery
+    {
+        println!("All tests passed successfully!");
+        Ok(())
+    } else {
+        deliver(&run, args, &mention_rules, notify_on_recovery)
+    };
+    // The run happened whether or not anyone heard about it: the next run
+    // classifies its failures and checks for recovery against this one.
+    if let Some(history) = &history {
+        history.record(&run)?;
+    }
+    delivered
 }
 
-fn send_slack_message(message: &str, webhook_url: &str) {
-    let client = reqwest::blocking::Client::new();
-    let response = client
-        .post(webhook_url)
-        .json(&SlackMessage {
-            text: message.to_owned(),
+/// Sends the run to every configured service, as a recovery message or by
+/// owner when asked to.
+fn deliver(
+    run: &TestRun,
+    args: &SendArgs,
+    mention_rules: &MentionRules,
+    notify_on_recovery: bool,
+) -> Result<()> {
+    let client = HttpClient::new(&args.delivery)?;
+    let notifiers = notifier::notifiers(&args.notifiers, &client);
+    if notify_on_recovery {
+        notifier::notify_all(&notifiers, |notifier| {
+            notifier.notify_recovery(run, &args.message)
         })
-        .send()
-        .context("Failed to send message to Slack")
-        .unwrap();
+    } else if let (Some(codeowners), Some(owners)) =
+        (&args.routing.codeowners_file, &args.routing.owners_file)
+    {
+        let codeowners = CodeOwners::load(codeowners)?;
+        let owners = Owners::load(owners)?;
+        if args.message.uses_thread() {
+            owners.check_threads()?;
+        }
+        send_by_owner(
+            run,
+            &codeowners,
+            &owners,
+            mention_rules,
+            args,
+            &notifiers,
+            &client,
+        )
+    } else {
+        notifier::notify_all(&notifiers, |notifier| notifier.notify(run, &args.message))
+    }
+}
+
+/// Sends each owner a message with only their failures, mentioning their
+/// user group and whoever the mention rules pick for those failures, and the
+/// failures without an owner to the default notifiers. Owners are always
+/// notified on Slack.
+/// With `--overview` the default destination gets every failure instead.
+///
+/// A failed delivery does not stop the others; the first error is returned.
+fn send_by_owner(
+    run: &TestRun,
+    codeowners: &CodeOwners,
+    owners: &Owners,
+    mention_rules: &MentionRules,
+    args: &SendArgs,
+    notifiers: &[Box<dyn Notifier + '_>],
+    client: &HttpClient,
+) -> Result<()> {
+    let routes = ownership::route(&run.failed_tests, codeowners, owners);
+    let mut result = Ok(());
+    for (name, failed_tests) in routes.owned {
+        let owner = owners
+            .get(&name)
+            .expect("failures are only routed to known owners");
+        // The summary stays the whole run's, so the title counts the owner's
+        // share of the failures.
+        let title = format!(
+            "{}: {} ({} of {} failures)",
+            args.message.title,
+            name,
+            failed_tests.len(),
+            run.failed_tests.len()
+        );
+        let mut owner_run = TestRun {
+            failed_tests,
+            summary: run.summary.clone(),
+            ..Default::default()
+        };
+        let user_group = owner.user_group.as_deref().map(slack::user_group_mention);
+        owner_run.mentions = mentions::dedup(
+            user_group
+                .into_iter()
+                .chain(mention_rules.mentions(&owner_run, &args.history.branch))
+                .collect(),
+        );
+        let message_args = MessageArgs {
+            title,
+            ..args.message.clone()
+        };
+        let sent =
+            Destination::for_owner(&name, owner, &args.notifiers.slack).and_then(|destination| {
+                SlackNotifier::new(destination, client).notify(&owner_run, &message_args)
+            });
+        if let Err(err) = sent {
+            eprintln!("Failed to notify {}: {}", name, err);
+            result = result.and(Err(err));
+        }
+    }
+
+    let default_run = if args.routing.overview {
+        run.clone()
+    } else {
+        let mut default_run = TestRun {
+            failed_tests: routes.unowned,
+            ..run.clone()
+        };
+        default_run.mentions = mention_rules.mentions(&default_run, &args.history.branch);
+        default_run
+    };
+    if args.routing.overview || !default_run.failed_tests.is_empty() || run.failed_tests.is_empty()
+    {
+        result = result.and(notifier::notify_all(notifiers, |notifier| {
+            notifier.notify(&default_run, &args.message)
+        }));
+    }
+    result
+}
+
+fn summarize(args: &SummarizeArgs) -> Result<()> {
+    let mut run = load_test_run(&args.report, &args.triage)?;
+    if let Some(history) = History::open(&args.history)? {
+        history.classify(&mut run);
+    }
+    let mention_rules = MentionRules::load(&args.mentions, args.history.history_file.is_some())?;
+    run.mentions = mention_rules.mentions(&run, &args.history.branch);
+    let messages = slack::build_slack_messages(&run, &args.message);
+    for message in &messages {
+        match args.format {
+            OutputFormat::Text => println!("{}", message.text),
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(message).expect("Slack messages serialize to JSON")
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn validate(args: &ReportArgs) -> Result<()> {
+    let paths = input::resolve_inputs(&args.inputs)?;
+    let reports = input::load_reports(&paths)?;
+    for report in &reports {
+        println!(
+            "{}: {} suite(s)",
+            report.path.display(),
+            report.suites.len()
+        );
+    }
+    println!("{} report file(s) are valid", reports.len());
+    Ok(())
+}
+
+fn diff(args: &DiffArgs) -> Result<()> {
+    let baseline = input::load_reports(&input::resolve_inputs(&args.baseline)?)?;
+    let reports = input::load_reports(&input::resolve_inputs(&args.report.inputs)?)?;
+    let mut comparison = Comparison::new(&baseline, &reports);
+    println!("{}", comparison);
 
-    if !response.status().is_success() {
-        let error_text = response.text();
-        panic!("Slack API error: {:?}", error_text);
+    if args.notifiers.is_empty() {
+        return Ok(());
+    }
+    let triage = Triage::load(&args.triage)?;
+    let mut run = test_run_from_reports(&reports, &triage);
+    // Quarantined tests that started failing do not alert anyone, as in `send`.
+    comparison.newly_failing.retain(|name| {
+        !run.quarantined_tests
+            .iter()
+            .any(|quarantined| &quarantined.failed.case.name == name)
+    });
+    if !comparison.has_regressions() {
+        println!("No regressions against the baseline");
+        return Ok(());
+    }
+    // Only the regressions are listed; the summary still covers the whole run.
+    run.failed_tests
+        .retain(|failed| comparison.newly_failing.contains(&failed.case.name));
+    run.flaky_tests.clear();
+    let client = HttpClient::new(&args.delivery)?;
+    let notifiers = notifier::notifiers(&args.notifiers, &client);
+    notifier::notify_all(&notifiers, |notifier| {
+        notifier.notify_regressions(&run, &comparison, &args.message)
+    })
+}
+
+fn load_test_run(args: &ReportArgs, triage: &TriageArgs) -> Result<TestRun> {
+    let triage = Triage::load(triage)?;
+    let paths = input::resolve_inputs(&args.inputs)?;
+    let reports = input::load_reports(&paths)?;
+    Ok(test_run_from_reports(&reports, &triage))
+}
+
+fn test_run_from_reports(reports: &[ReportFile], triage: &Triage) -> TestRun {
+    let mut run = TestRun {
+        summary: Summary::from_reports(reports),
+        expired_quarantine: triage.quarantine.expired(),
+        ..Default::default()
+    };
+    for report in reports {
+        collect_failed_tests(&report.suites, &[], report, triage, &mut run);
+    }
+    run
+}
+
+/// Sorts the test cases of `test_suites` into failed, quarantined, passed and
+/// flaky tests, attributing failures to known issues.
+fn collect_failed_tests(
+    test_suites: &[TestSuite],
+    suite_path: &[String],
+    report: &ReportFile,
+    triage: &Triage,
+    run: &mut TestRun,
+) {
+    for suite in test_suites {
+        let mut suite_path = suite_path.to_vec();
+        suite_path.push(suite.name.clone());
+        collect_failed_tests(&suite.suites, &suite_path, report, triage, run);
+        for case in &suite.cases {
+            if has_failures(case) {
+                let mut failed = FailedTest {
+                    case: case.clone(),
+                    source: report.path.clone(),
+                    suite_path: suite_path.clone(),
+                    history: None,
+                    known_issue: None,
+                };
+                failed.known_issue = triage.known_issues.find(&failed).cloned();
+                match triage.quarantine.find(case) {
+                    Some(entry) => run.quarantined_tests.push(QuarantinedTest {
+                        failed,
+                        entry: entry.clone(),
+                    }),
+                    None => run.failed_tests.push(failed),
+                }
+                continue;
+            }
+            if let TestStatus::Success = case.status {
+                run.passed_tests.insert(case.name.clone());
+                if let [first_failure, ..] = report.reruns(case) {
+                    run.flaky_tests.push(FlakyTest {
+                        case: case.clone(),
+                        attempts: report.reruns(case).len() + 1,
+                        first_failure: first_failure.clone(),
+                    });
+                }
+            }
+        }
     }
+}
 
-    println!("Results sent to Slack");
+fn has_failures(case: &TestCase) -> bool {
+    match case.status {
+        TestStatus::Success => false,
+        TestStatus::Error(_) => true,
+        TestStatus::Failure(_) => true,
+        TestStatus::Skipped(_) => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use error::Error;
     use junit_parser::{TestCase, TestError, TestFailure, TestSkipped, TestStatus};
-    use mockito::Server;
 
     #[test]
     fn test_has_failures() {
@@ -140,36 +624,238 @@ mod tests {
     }
 
     #[test]
-    fn test_append_case_info() {
-        let case = TestCase {
-            name: "test_method".to_string(),
-            classname: Some("com.example.TestClass".to_string()),
-            status: TestStatus::Failure(TestFailure {
-                message: "".to_string(),
-                text: "".to_string(),
-                failure_type: "".to_string(),
-            }),
-            time: 2.5,
-            ..Default::default()
+    fn test_collect_failed_tests_merges_reports() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("TEST-first.xml");
+        let second = dir.path().join("TEST-second.xml");
+        std::fs::write(
+            &first,
+            r#"<testsuite name="first"><testcase name="ok"/><testcase name="broken"><failure message="boom"/></testcase><testcase name="flaky"><flakyFailure message="once"/><flakyFailure message="twice"/></testcase></testsuite>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &second,
+            r#"<testsuites><testsuite name="second"><testsuite name="nested"><testcase name="crashed"><error/></testcase></testsuite></testsuite></testsuites>"#,
+        )
+        .unwrap();
+
+        let run = load_test_run(
+            &ReportArgs {
+                inputs: vec![dir.path().display().to_string()],
+            },
+            &TriageArgs::default(),
+        )
+        .unwrap();
+
+        let found: Vec<_> = run
+            .failed_tests
+            .iter()
+            .map(|failed| (failed.case.name.as_str(), failed.source.clone()))
+            .collect();
+        assert_eq!(found, vec![("broken", first), ("crashed", second)]);
+        assert_eq!(run.failed_tests[1].suite_path, vec!["second", "nested"]);
+        assert_eq!(
+            run.failed_tests[1].group(GroupBy::SuitePath).as_deref(),
+            Some("second › nested")
+        );
+        assert_eq!(
+            run.failed_tests[1].group(GroupBy::Suite).as_deref(),
+            Some("second")
+        );
+        assert_eq!(
+            run.failed_tests[1].group(GroupBy::Package).as_deref(),
+            Some("(none)")
+        );
+        assert_eq!(run.failed_tests[1].group(GroupBy::None), None);
+
+        assert_eq!(run.flaky_tests.len(), 1);
+        let flaky = &run.flaky_tests[0];
+        assert_eq!(flaky.case.name, "flaky");
+        assert_eq!(flaky.attempts, 3);
+        assert_eq!(flaky.first_failure.message, "once");
+    }
+
+    #[test]
+    fn test_collect_failed_tests_mutes_quarantined_tests() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("junit.xml");
+        let quarantine = dir.path().join("quarantine.toml");
+        std::fs::write(
+            &report,
+            r#"<testsuite name="s"><testcase classname="A" name="known"><failure/></testcase><testcase classname="B" name="stale"><failure/></testcase></testsuite>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &quarantine,
+            "[[quarantine]]\nclassname = \"A\"\nticket = \"PAY-12\"\nexpires = 2999-01-01\n\n\
+             [[quarantine]]\nclassname = \"B\"\nexpires = 2000-01-01\n",
+        )
+        .unwrap();
+
+        let run = load_test_run(
+            &ReportArgs {
+                inputs: vec![report.display().to_string()],
+            },
+            &TriageArgs {
+                quarantine_file: Some(quarantine),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(run.quarantined_tests.len(), 1);
+        assert_eq!(run.quarantined_tests[0].failed.case.name, "A::known");
+        assert_eq!(
+            run.quarantined_tests[0].entry.ticket.as_deref(),
+            Some("PAY-12")
+        );
+        assert_eq!(run.failed_tests.len(), 1);
+        assert_eq!(run.failed_tests[0].case.name, "B::stale");
+        assert_eq!(run.expired_quarantine.len(), 1);
+    }
+
+    #[test]
+    fn test_send_records_history_when_delivery_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = mockito::Server::new();
+        let report = dir.path().join("junit.xml");
+        let history = dir.path().join("runs.jsonl");
+        std::fs::write(
+            &report,
+            r#"<testsuite name="s"><testcase name="broken"><failure/></testcase></testsuite>"#,
+        )
+        .unwrap();
+        let webhook = server.mock("POST", "/webhook").with_status(400).create();
+
+        let cli = Cli::try_parse_from([
+            "junit_to_slack_notification",
+            report.to_str().unwrap(),
+            "--webhook-url",
+            &format!("{}/webhook", server.url()),
+            "--history-file",
+            history.to_str().unwrap(),
+        ])
+        .unwrap();
+        let Ok(Command::Send(args)) = cli.into_command() else {
+            panic!("expected the send command");
         };
 
-        let mut message = String::new();
-        append_case_info(&mut message, &case);
-        assert!(message.contains(&case.name));
+        let err = send(&args).unwrap_err();
+        assert!(matches!(err, Error::Delivery { .. }));
+        webhook.assert();
+        let recorded = std::fs::read_to_string(&history).unwrap();
+        assert_eq!(recorded.lines().count(), 1);
+        assert!(recorded.contains("broken"));
     }
 
     #[test]
-    fn test_send_slack_message_success() {
-        let mut server = Server::new();
-        let mock_url = server.url();
-
-        let mock = server
-            .mock("POST", "/")
-            .with_status(200)
-            .with_header("content-type", "application/json")
+    fn test_diff_ignores_quarantined_regressions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = mockito::Server::new();
+        let baseline = dir.path().join("baseline.xml");
+        let report = dir.path().join("junit.xml");
+        let quarantine = dir.path().join("quarantine.toml");
+        std::fs::write(
+            &baseline,
+            r#"<testsuite name="s"><testcase classname="A" name="muted"/></testsuite>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &report,
+            r#"<testsuite name="s"><testcase classname="A" name="muted"><failure/></testcase></testsuite>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &quarantine,
+            "[[quarantine]]\nclassname = \"A\"\nexpires = 2999-01-01\n",
+        )
+        .unwrap();
+        let webhook = server.mock("POST", "/webhook").expect(0).create();
+
+        let cli = Cli::try_parse_from([
+            "junit_to_slack_notification",
+            "diff",
+            report.to_str().unwrap(),
+            "--baseline",
+            baseline.to_str().unwrap(),
+            "--webhook-url",
+            &format!("{}/webhook", server.url()),
+            "--quarantine-file",
+            quarantine.to_str().unwrap(),
+        ])
+        .unwrap();
+        let Ok(Command::Diff(args)) = cli.into_command() else {
+            panic!("expected the diff command");
+        };
+
+        diff(&args).unwrap();
+        webhook.assert();
+    }
+
+    #[test]
+    fn test_send_by_owner_fans_out_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = mockito::Server::new();
+        let codeowners = dir.path().join("CODEOWNERS");
+        let owners = dir.path().join("owners.toml");
+        std::fs::write(&codeowners, "com/acme/payments/ @acme/payments\n").unwrap();
+        std::fs::write(
+            &owners,
+            format!(
+                "[owners.\"@acme/payments\"]\nwebhook_url = \"{}/payments\"\nuser_group = \"S0123\"\n",
+                server.url()
+            ),
+        )
+        .unwrap();
+        let payments = server
+            .mock("POST", "/payments")
+            .match_body(mockito::Matcher::Regex(
+                r"@acme/payments \(1 of 2 failures\).*RefundTest.*<!subteam\^S0123>".to_string(),
+            ))
+            .create();
+        let default = server
+            .mock("POST", "/default")
+            .match_body(mockito::Matcher::Regex("SearchTest".to_string()))
             .create();
 
-        send_slack_message("test message", &mock_url);
-        mock.assert();
+        let cli = Cli::try_parse_from([
+            "junit_to_slack_notification",
+            "--webhook-url",
+            &format!("{}/default", server.url()),
+            "--codeowners-file",
+            codeowners.to_str().unwrap(),
+            "--owners-file",
+            owners.to_str().unwrap(),
+        ])
+        .unwrap();
+        let Ok(Command::Send(args)) = cli.into_command() else {
+            panic!("expected the send command");
+        };
+        let failed_test = |classname: &str| {
+            let mut failed = fixtures::failed_test(&format!("{}::test", classname));
+            failed.case.classname = Some(classname.to_string());
+            failed
+        };
+        let run = TestRun {
+            failed_tests: vec![
+                failed_test("com.acme.payments.RefundTest"),
+                failed_test("com.acme.search.SearchTest"),
+            ],
+            ..Default::default()
+        };
+        let client = http::tests::test_client();
+
+        send_by_owner(
+            &run,
+            &CodeOwners::load(&codeowners).unwrap(),
+            &Owners::load(&owners).unwrap(),
+            &MentionRules::default(),
+            &args,
+            &notifier::notifiers(&args.notifiers, &client),
+            &client,
+        )
+        .unwrap();
+        payments.assert();
+        default.assert();
     }
 }
@@ -0,0 +1,472 @@
+use crate::cli::{ChatOverrideArgs, MessageArgs};
+use crate::diff::Comparison;
+use crate::error::Result;
+use crate::http::HttpClient;
+use crate::notifier::{self, ListSection, Notifier, ALL_PASSED, BACK_TO_GREEN};
+use crate::summary::{format_duration, format_pass_rate, Summary};
+use crate::{FailedTest, TestRun};
+use reqwest::Url;
+use serde::Serialize;
+
+/// Chat services with Slack-like incoming webhooks that render standard
+/// Markdown instead of Slack's `mrkdwn`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flavor {
+    Mattermost,
+    RocketChat,
+}
+
+impl Flavor {
+    fn name(self) -> &'static str {
+        match self {
+            Flavor::Mattermost => "Mattermost",
+            Flavor::RocketChat => "Rocket.Chat",
+        }
+    }
+
+    /// Longest message text the service accepts with its default settings.
+    fn max_text_length(self) -> usize {
+        match self {
+            Flavor::Mattermost => 16_383,
+            Flavor::RocketChat => 5_000,
+        }
+    }
+}
+
+/// Payload accepted by Mattermost incoming webhooks.
+#[derive(Serialize)]
+struct MattermostMessage<'a> {
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon_url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon_emoji: Option<&'a str>,
+}
+
+/// Payload accepted by Rocket.Chat incoming webhooks, which name the
+/// username and icon overrides differently.
+#[derive(Serialize)]
+struct RocketChatMessage<'a> {
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avatar: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emoji: Option<&'a str>,
+}
+
+/// Posts Markdown messages to a Mattermost or Rocket.Chat webhook.
+pub struct MarkdownNotifier<'a> {
+    flavor: Flavor,
+    url: Url,
+    overrides: &'a ChatOverrideArgs,
+    client: &'a HttpClient,
+}
+
+impl<'a> MarkdownNotifier<'a> {
+    pub fn new(
+        flavor: Flavor,
+        url: Url,
+        overrides: &'a ChatOverrideArgs,
+        client: &'a HttpClient,
+    ) -> Self {
+        MarkdownNotifier {
+            flavor,
+            url,
+            overrides,
+            client,
+        }
+    }
+
+    fn send(&self, text: &str) -> Result<()> {
+        let overrides = self.overrides;
+        let url = self.url.as_str();
+        let service = self.flavor.name();
+        match self.flavor {
+            Flavor::Mattermost => {
+                let message = MattermostMessage {
+                    text,
+                    channel: overrides.chat_channel.as_deref(),
+                    username: overrides.chat_username.as_deref(),
+                    icon_url: overrides.chat_icon_url.as_ref().map(Url::as_str),
+                    icon_emoji: overrides.chat_icon_emoji.as_deref(),
+                };
+                self.client.post_json(url, &message, service)?
+            }
+            Flavor::RocketChat => {
+                let message = RocketChatMessage {
+                    text,
+                    channel: overrides.chat_channel.as_deref(),
+                    alias: overrides.chat_username.as_deref(),
+                    avatar: overrides.chat_icon_url.as_ref().map(Url::as_str),
+                    emoji: overrides.chat_icon_emoji.as_deref(),
+                };
+                self.client.post_json(url, &message, service)?
+            }
+        };
+        println!("Results sent to {}", service);
+        Ok(())
+    }
+}
+
+impl Notifier for MarkdownNotifier<'_> {
+    fn notify(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(&format_markdown_message(run, args, self.flavor))
+    }
+
+    fn notify_recovery(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(&format_recovery_message(run, args, self.flavor))
+    }
+
+    fn notify_regressions(
+        &self,
+        run: &TestRun,
+        comparison: &Comparison,
+        args: &MessageArgs,
+    ) -> Result<()> {
+        let counts = format!(
+            "\n**Compared with the baseline**\n{}\n",
+            comparison.counts()
+        );
+        self.send(&format_message_with_sections(
+            run,
+            args,
+            self.flavor,
+            &counts,
+        ))
+    }
+}
+
+/// Formats the run in standard Markdown: the summary and the failures as
+/// tables, followed by the stack traces of the listed failures.
+///
+/// At most `--max-failures` failures are listed, fewer if the message would
+/// not fit in the service's size limit; the rest are counted in an "…and N
+/// more" line. Flaky tests, quarantined failures and tests fixed since the
+/// previous run are listed at the end, up to `--max-failures` each. Should
+/// those lists alone be too long, the message is cut at the limit.
+pub fn format_markdown_message(run: &TestRun, args: &MessageArgs, flavor: Flavor) -> String {
+    format_message_with_sections(run, args, flavor, "")
+}
+
+/// Like [`format_markdown_message`], with `extra` text after the lists.
+fn format_message_with_sections(
+    run: &TestRun,
+    args: &MessageArgs,
+    flavor: Flavor,
+    extra: &str,
+) -> String {
+    let mut sections = format_sections(&notifier::list_sections(run), args.max_failures);
+    sections.push_str(extra);
+    let max_length = flavor
+        .max_text_length()
+        .saturating_sub(sections.chars().count());
+    let text = format_failures(run, args, max_length);
+    notifier::truncate(&(text + &sections), flavor.max_text_length())
+}
+
+/// A message for a run that passes again, listing the tests it fixed.
+pub fn format_recovery_message(run: &TestRun, args: &MessageArgs, flavor: Flavor) -> String {
+    let text = format!(
+        "#### {}\n\n{}\n{}\n{}",
+        args.title,
+        summary_table(&run.summary),
+        BACK_TO_GREEN,
+        format_sections(&notifier::recovery_sections(run), args.max_failures)
+    );
+    notifier::truncate(&text, flavor.max_text_length())
+}
+
+/// The summary, a table row per failure and their stack traces, listing up
+/// to `--max-failures` failures and as many as fit in `max_length`
+/// characters with the "…and N more" line.
+fn format_failures(run: &TestRun, args: &MessageArgs, max_length: usize) -> String {
+    const TABLE_HEADER: &str = "\n| Test | Failure | Report |\n|:--|:--|:--|\n";
+    let mut text = format!("#### {}\n\n{}", args.title, summary_table(&run.summary));
+    if run.summary.all_passed() {
+        text.push_str(&format!("\n{}\n", ALL_PASSED));
+    }
+    let more = |remaining: usize| format!("\n…and {} more\n", remaining);
+
+    let mut length = text.chars().count();
+    let mut rows = String::new();
+    let mut stack_traces = String::new();
+    let mut listed = 0;
+    for failed in run.failed_tests.iter().take(args.max_failures) {
+        let row = failure_row(failed);
+        let stack_trace = stack_trace_block(failed, args.stack_trace_lines);
+        let mut added = row.chars().count() + stack_trace.chars().count();
+        if listed == 0 {
+            added += TABLE_HEADER.len();
+        }
+        let remaining = run.failed_tests.len() - listed - 1;
+        let reserved = if remaining > 0 {
+            more(remaining).chars().count()
+        } else {
+            0
+        };
+        if length + added + reserved > max_length {
+            break;
+        }
+        length += added;
+        rows.push_str(&row);
+        stack_traces.push_str(&stack_trace);
+        listed += 1;
+    }
+
+    if listed > 0 {
+        text.push_str(TABLE_HEADER);
+        text.push_str(&rows);
+    }
+    let remaining = run.failed_tests.len() - listed;
+    if remaining > 0 {
+        text.push_str(&more(remaining));
+    }
+    text.push_str(&stack_traces);
+    text
+}
+
+/// The test's name and the top of its stack trace in a code block, or
+/// nothing without a stack trace.
+fn stack_trace_block(failed: &FailedTest, stack_trace_lines: usize) -> String {
+    let stack_trace = failed.stack_trace(stack_trace_lines);
+    if stack_trace.is_empty() {
+        return String::new();
+    }
+    format!(
+        "\n{}\n{}\n",
+        code_span(&failed.case.name),
+        code_block(&stack_trace.join("\n"))
+    )
+}
+
+fn summary_table(summary: &Summary) -> String {
+    let pass_rate = summary
+        .pass_rate()
+        .map_or("-".to_string(), format_pass_rate);
+    format!(
+        "| Total | Passed | Failed | Errored | Skipped | Flaky | Pass rate | Duration |\n\
+         |--:|--:|--:|--:|--:|--:|--:|--:|\n\
+         | {} | {} | {} | {} | {} | {} | {} | {} |\n",
+        summary.total,
+        summary.passed,
+        summary.failed,
+        summary.errored,
+        summary.skipped,
+        summary.flaky,
+        pass_rate,
+        format_duration(summary.duration)
+    )
+}
+
+fn failure_row(failed: &FailedTest) -> String {
+    let mut name = code_span(&escape_cell(&failed.case.name));
+    if let Some(status) = failed.history {
+        name.push_str(&format!(" **{}**", status));
+    }
+    let mut reason = escape_cell(&failed.reason());
+    if let Some(issue) = &failed.known_issue {
+        reason.push_str(&match &issue.url {
+            Some(url) => format!(" (known issue: [{}]({}))", escape_cell(&issue.label), url),
+            None => format!(" (known issue: {})", escape_cell(&issue.label)),
+        });
+    }
+    format!(
+        "| {} | {} | `{}` |\n",
+        name,
+        reason,
+        failed.source.display()
+    )
+}
+
+/// Lists each section under a bold heading, up to `max_items` entries.
+fn format_sections(sections: &[ListSection], max_items: usize) -> String {
+    let mut text = String::new();
+    for section in sections {
+        text.push_str(&format!("\n**{}**\n", section.heading()));
+        for item in section.items.iter().take(max_items) {
+            text.push_str(&format!("- {}", code_span(item.name)));
+            match (&item.note, item.link) {
+                (Some(note), Some(url)) => text.push_str(&format!(" ([{}]({}))", note, url)),
+                (Some(note), None) => text.push_str(&format!(" ({})", note)),
+                _ => {}
+            }
+            text.push('\n');
+        }
+        if section.items.len() > max_items {
+            text.push_str(&format!("…and {} more\n", section.items.len() - max_items));
+        }
+    }
+    text
+}
+
+/// Inline code delimited by more backticks than `text` has in a row, so
+/// backticks in it show as written.
+fn code_span(text: &str) -> String {
+    match longest_backtick_run(text) {
+        0 => format!("`{}`", text),
+        longest => {
+            let ticks = "`".repeat(longest + 1);
+            format!("{} {} {}", ticks, text, ticks)
+        }
+    }
+}
+
+/// A code block fenced with more backticks than `text` has in a row, so a
+/// "```" in it does not end the block.
+fn code_block(text: &str) -> String {
+    let fence = "`".repeat((longest_backtick_run(text) + 1).max(3));
+    format!("{}\n{}\n{}", fence, text, fence)
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// Keeps text on one line of a table cell.
+fn escape_cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{message_args, quarantined_test, test_run, test_run_with_stack_trace};
+    use crate::history::FailureStatus;
+    use crate::http::tests::test_client;
+    use junit_parser::TestStatus;
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    #[test]
+    fn test_format_markdown_message() {
+        let mut run = test_run(2);
+        let failed = &mut run.failed_tests[0];
+        failed.case.name = "test[a|b]".to_string();
+        failed.history = Some(FailureStatus::New);
+        if let TestStatus::Failure(failure) = &mut failed.case.status {
+            failure.message = "a | b\nc".to_string();
+        }
+        run.quarantined_tests = vec![quarantined_test("test_muted")];
+        run.fixed_tests = vec!["test_fixed".to_string()];
+
+        let text = format_markdown_message(&run, &message_args(1), Flavor::Mattermost);
+        assert_eq!(
+            text,
+            "#### Nightly\n\
+             \n| Total | Passed | Failed | Errored | Skipped | Flaky | Pass rate | Duration |\n\
+             |--:|--:|--:|--:|--:|--:|--:|--:|\n\
+             | 3 | 1 | 2 | 0 | 0 | 0 | 33.3% | 1.0s |\n\
+             \n| Test | Failure | Report |\n\
+             |:--|:--|:--|\n\
+             | `test[a\\|b]` **NEW** | AssertionError: a \\| b c | `junit.xml` |\n\
+             \n…and 1 more\n\
+             \n`test[a|b]`\n```\nAssertionError\n\tat test_0\n```\n\
+             \n**Quarantined failures (1)**\n\
+             - `test_muted` ([PAY-12](https://jira.example.com/browse/PAY-12))\n\
+             \n**Fixed since last run (1)**\n\
+             - `test_fixed`\n"
+        );
+    }
+
+    #[test]
+    fn test_format_markdown_message_fits_size_limit() {
+        let args = message_args(100);
+        let text = format_markdown_message(
+            &test_run_with_stack_trace(100, 300),
+            &args,
+            Flavor::RocketChat,
+        );
+        assert!(text.chars().count() <= Flavor::RocketChat.max_text_length());
+        assert!(text.contains("more\n"));
+
+        let text = format_markdown_message(
+            &test_run_with_stack_trace(100, 300),
+            &args,
+            Flavor::Mattermost,
+        );
+        assert!(text.chars().count() <= Flavor::Mattermost.max_text_length());
+    }
+
+    #[test]
+    fn test_format_markdown_message_fences_backticks() {
+        let mut run = test_run(1);
+        let failed = &mut run.failed_tests[0];
+        failed.case.name = "test `x`".to_string();
+        if let TestStatus::Failure(failure) = &mut failed.case.status {
+            failure.text = "Error: ```\n\tat run".to_string();
+        }
+
+        let text = format_markdown_message(&run, &message_args(20), Flavor::Mattermost);
+        assert!(text.contains("| `` test `x` `` |"));
+        assert!(text.ends_with("\n`` test `x` ``\n````\nError: ```\n\tat run\n````\n"));
+    }
+
+    #[test]
+    fn test_format_markdown_message_limits_long_lists() {
+        let mut run = test_run_with_stack_trace(1, 300);
+        run.fixed_tests = (0..1_000)
+            .map(|index| format!("com.example.SomeLongTestClassName::test_{}", index))
+            .collect();
+
+        let text = format_markdown_message(&run, &message_args(20), Flavor::RocketChat);
+        assert!(text.ends_with("- `com.example.SomeLongTestClassName::test_19`\n…and 980 more\n"));
+
+        let text = format_markdown_message(&run, &message_args(1_000), Flavor::RocketChat);
+        assert_eq!(
+            text.chars().count(),
+            Flavor::RocketChat.max_text_length() - 3
+        );
+        assert!(text.contains("…and 1 more\n"));
+    }
+
+    #[test]
+    fn test_notifiers_apply_overrides() {
+        let mut server = Server::new();
+        let mattermost = server
+            .mock("POST", "/mattermost")
+            .match_body(Matcher::PartialJson(json!({
+                "channel": "ci",
+                "username": "junit",
+                "icon_emoji": ":test_tube:",
+            })))
+            .create();
+        let rocket_chat = server
+            .mock("POST", "/rocketchat")
+            .match_body(Matcher::PartialJson(json!({
+                "channel": "ci",
+                "alias": "junit",
+                "emoji": ":test_tube:",
+            })))
+            .create();
+        let overrides = ChatOverrideArgs {
+            chat_channel: Some("ci".to_string()),
+            chat_username: Some("junit".to_string()),
+            chat_icon_url: None,
+            chat_icon_emoji: Some(":test_tube:".to_string()),
+        };
+        let client = test_client();
+
+        for (flavor, path) in [
+            (Flavor::Mattermost, "/mattermost"),
+            (Flavor::RocketChat, "/rocketchat"),
+        ] {
+            let url = format!("{}{}", server.url(), path).parse().unwrap();
+            MarkdownNotifier::new(flavor, url, &overrides, &client)
+                .notify(&test_run(1), &message_args(20))
+                .unwrap();
+        }
+        mattermost.assert();
+        rocket_chat.assert();
+    }
+}
@@ -0,0 +1,364 @@
+use crate::cli::MentionArgs;
+use crate::date::{local_date, today};
+use crate::error::{Error, Result};
+use crate::history::FailureStatus;
+use crate::slack::{user_group_mention, user_mention};
+use crate::TestRun;
+use glob::Pattern;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+use toml::value::{Date, Datetime};
+
+static USER_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[UW][A-Z0-9]+$").unwrap());
+static USER_GROUP_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^S[A-Z0-9]+$").unwrap());
+
+/// Layout of the mentions file. Every condition of a rule must hold for it
+/// to apply; a rule without conditions applies to every failing run.
+///
+/// ```toml
+/// [[mention]]
+/// users = ["U0123ABCD"]
+/// user_groups = ["S0456EFGH"]
+/// on_call = true
+/// suite = "integration*"
+/// failures_above = 10
+/// new_failure_on_branch = "main"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MentionsFile {
+    #[serde(default)]
+    mention: Vec<RawRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    #[serde(default)]
+    users: Vec<String>,
+    #[serde(default)]
+    user_groups: Vec<String>,
+    #[serde(default)]
+    on_call: bool,
+    suite: Option<String>,
+    failures_above: Option<usize>,
+    new_failure_on_branch: Option<String>,
+}
+
+/// Layout of the on-call schedule, with inclusive dates:
+///
+/// ```toml
+/// [[shift]]
+/// user = "U0123ABCD"
+/// start = 2026-10-12
+/// end = 2026-10-18
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScheduleFile {
+    #[serde(default)]
+    shift: Vec<Shift>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Shift {
+    user: String,
+    start: Datetime,
+    end: Datetime,
+}
+
+struct Rule {
+    mentions: Vec<String>,
+    on_call: bool,
+    suite: Option<Pattern>,
+    failures_above: Option<usize>,
+    new_failure_on_branch: Option<String>,
+}
+
+impl Rule {
+    fn applies(&self, run: &TestRun, branch: &str) -> bool {
+        let suite = self.suite.as_ref().is_none_or(|pattern| {
+            run.failed_tests
+                .iter()
+                .any(|failed| failed.suite_path.iter().any(|suite| pattern.matches(suite)))
+        });
+        let count = self
+            .failures_above
+            .is_none_or(|threshold| run.failed_tests.len() > threshold);
+        let new_failure = self.new_failure_on_branch.as_ref().is_none_or(|name| {
+            name == branch
+                && run
+                    .failed_tests
+                    .iter()
+                    .any(|failed| failed.history == Some(FailureStatus::New))
+        });
+        suite && count && new_failure
+    }
+}
+
+/// Rules choosing who is mentioned in the message about a failing run.
+#[derive(Default)]
+pub struct MentionRules {
+    rules: Vec<Rule>,
+    /// Mention of whoever is on call today.
+    on_call: Option<String>,
+}
+
+impl MentionRules {
+    /// Reads the mentions file and the on-call schedule, or returns no rules
+    /// without a mentions file. `has_history` tells whether failures are
+    /// classified against a history file, which new failure rules rely on.
+    pub fn load(args: &MentionArgs, has_history: bool) -> Result<Self> {
+        let Some(path) = &args.mentions_file else {
+            return Ok(MentionRules::default());
+        };
+        let on_call = match &args.on_call_file {
+            Some(schedule) => on_call(schedule, today())?,
+            None => None,
+        };
+        let content = fs::read_to_string(path).map_err(|err| Error::config_file(path, err))?;
+        MentionRules::parse(&content, on_call, args.on_call_file.is_some(), has_history).map_err(
+            |err| Error::Config(format!("Invalid mentions file {}: {}", path.display(), err)),
+        )
+    }
+
+    fn parse(
+        content: &str,
+        on_call: Option<String>,
+        has_schedule: bool,
+        has_history: bool,
+    ) -> std::result::Result<Self, String> {
+        let file: MentionsFile = toml::from_str(content).map_err(|err| err.to_string())?;
+        let rules = file
+            .mention
+            .into_iter()
+            .map(|raw| {
+                if raw.users.is_empty() && raw.user_groups.is_empty() && !raw.on_call {
+                    return Err("a rule needs users, user_groups or on_call".to_string());
+                }
+                if raw.on_call && !has_schedule {
+                    return Err("on_call rules need --on-call-file".to_string());
+                }
+                if raw.new_failure_on_branch.is_some() && !has_history {
+                    return Err("new_failure_on_branch rules need --history-file".to_string());
+                }
+                let mut mentions = vec![];
+                for user in &raw.users {
+                    if !USER_ID.is_match(user) {
+                        return Err(format!("{} is not a Slack user ID like U0123ABCD", user));
+                    }
+                    mentions.push(user_mention(user));
+                }
+                for user_group in &raw.user_groups {
+                    if !USER_GROUP_ID.is_match(user_group) {
+                        return Err(format!(
+                            "{} is not a Slack user group ID like S0123ABCD",
+                            user_group
+                        ));
+                    }
+                    mentions.push(user_group_mention(user_group));
+                }
+                let suite = raw
+                    .suite
+                    .map(|suite| {
+                        Pattern::new(&suite)
+                            .map_err(|err| format!("invalid pattern {}: {}", suite, err))
+                    })
+                    .transpose()?;
+                Ok(Rule {
+                    mentions,
+                    on_call: raw.on_call,
+                    suite,
+                    failures_above: raw.failures_above,
+                    new_failure_on_branch: raw.new_failure_on_branch,
+                })
+            })
+            .collect::<std::result::Result<_, String>>()?;
+        Ok(MentionRules { rules, on_call })
+    }
+
+    /// Mentions of every rule that applies to a run on `branch`, each listed
+    /// once. A run without failures mentions nobody.
+    pub fn mentions(&self, run: &TestRun, branch: &str) -> Vec<String> {
+        if run.failed_tests.is_empty() {
+            return vec![];
+        }
+        let mentions = self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies(run, branch))
+            .flat_map(|rule| {
+                let on_call = self.on_call.iter().filter(|_| rule.on_call);
+                rule.mentions.iter().chain(on_call).cloned()
+            })
+            .collect();
+        dedup(mentions)
+    }
+}
+
+/// Removes repeated mentions, keeping the first of each.
+pub fn dedup(mentions: Vec<String>) -> Vec<String> {
+    let mut unique: Vec<String> = vec![];
+    for mention in mentions {
+        if !unique.contains(&mention) {
+            unique.push(mention);
+        }
+    }
+    unique
+}
+
+/// Mention of the user whose shift covers `today`, if any.
+fn on_call(path: &Path, today: Date) -> Result<Option<String>> {
+    let content = fs::read_to_string(path).map_err(|err| Error::config_file(path, err))?;
+    parse_schedule(&content, today).map_err(|err| {
+        Error::Config(format!(
+            "Invalid on-call schedule {}: {}",
+            path.display(),
+            err
+        ))
+    })
+}
+
+fn parse_schedule(content: &str, today: Date) -> std::result::Result<Option<String>, String> {
+    let file: ScheduleFile = toml::from_str(content).map_err(|err| err.to_string())?;
+    if let Some(shift) = file
+        .shift
+        .iter()
+        .find(|shift| !USER_ID.is_match(&shift.user))
+    {
+        return Err(format!(
+            "{} is not a Slack user ID like U0123ABCD",
+            shift.user
+        ));
+    }
+    for shift in file.shift {
+        let start = local_date(shift.start).map_err(|err| format!("invalid start: {}", err))?;
+        let end = local_date(shift.end).map_err(|err| format!("invalid end: {}", err))?;
+        if start <= today && today <= end {
+            return Ok(Some(user_mention(&shift.user)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, FailedTest};
+
+    fn failed_test(suite: &str, history: Option<FailureStatus>) -> FailedTest {
+        let mut failed = fixtures::failed_test("test");
+        failed.suite_path = vec!["all".to_string(), suite.to_string()];
+        failed.history = history;
+        failed
+    }
+
+    fn run(failed_tests: Vec<FailedTest>) -> TestRun {
+        TestRun {
+            failed_tests,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_mentions_of_matching_rules_are_deduplicated() {
+        let rules = MentionRules::parse(
+            r#"
+            [[mention]]
+            users = ["U01"]
+            suite = "integration*"
+
+            [[mention]]
+            users = ["U01", "U02"]
+            user_groups = ["S01"]
+            failures_above = 1
+
+            [[mention]]
+            user_groups = ["S02"]
+            on_call = true
+            new_failure_on_branch = "main"
+            "#,
+            Some(user_mention("U09")),
+            true,
+            true,
+        )
+        .unwrap();
+
+        let one = run(vec![failed_test("integration-db", None)]);
+        assert_eq!(rules.mentions(&one, "main"), vec!["<@U01>"]);
+
+        let two = run(vec![
+            failed_test("integration-db", None),
+            failed_test("unit", Some(FailureStatus::New)),
+        ]);
+        assert_eq!(
+            rules.mentions(&two, "main"),
+            vec![
+                "<@U01>",
+                "<@U02>",
+                "<!subteam^S01>",
+                "<!subteam^S02>",
+                "<@U09>"
+            ]
+        );
+        assert_eq!(
+            rules.mentions(&two, "feature"),
+            vec!["<@U01>", "<@U02>", "<!subteam^S01>"]
+        );
+        assert!(rules.mentions(&run(vec![]), "main").is_empty());
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_rules() {
+        assert!(
+            MentionRules::parse("[[mention]]\nusers = [\"@alice\"]", None, false, false).is_err()
+        );
+        assert!(
+            MentionRules::parse("[[mention]]\nuser_groups = [\"U01\"]", None, false, false)
+                .is_err()
+        );
+        assert!(MentionRules::parse("[[mention]]\nsuite = \"a\"", None, false, false).is_err());
+        assert!(MentionRules::parse("[[mention]]\non_call = true", None, false, false).is_err());
+        let new_failure = "[[mention]]\nusers = [\"U01\"]\nnew_failure_on_branch = \"main\"";
+        assert!(MentionRules::parse(new_failure, None, false, false).is_err());
+        assert!(MentionRules::parse(new_failure, None, false, true).is_ok());
+    }
+
+    #[test]
+    fn test_schedule_finds_todays_shift() {
+        let schedule = r#"
+            [[shift]]
+            user = "U01"
+            start = 2026-10-05
+            end = 2026-10-11
+
+            [[shift]]
+            user = "U02"
+            start = 2026-10-12
+            end = 2026-10-18
+            "#;
+        let day = |day| Date {
+            year: 2026,
+            month: 10,
+            day,
+        };
+        assert_eq!(
+            parse_schedule(schedule, day(11)).unwrap().as_deref(),
+            Some("<@U01>")
+        );
+        assert_eq!(
+            parse_schedule(schedule, day(12)).unwrap().as_deref(),
+            Some("<@U02>")
+        );
+        assert_eq!(parse_schedule(schedule, day(19)).unwrap(), None);
+        assert!(parse_schedule(
+            "[[shift]]\nuser = \"alice\"\nstart = 2026-10-01\nend = 2026-10-02",
+            day(1)
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use crate::cli::{MessageArgs, NotifierArgs};
+use crate::diff::Comparison;
+use crate::discord::DiscordNotifier;
+use crate::email::EmailNotifier;
+use crate::error::Result;
+use crate::google_chat::GoogleChatNotifier;
+use crate::http::HttpClient;
+use crate::markdown::{Flavor, MarkdownNotifier};
+use crate::slack::{self, Destination};
+use crate::teams::TeamsNotifier;
+use crate::TestRun;
+use std::fmt;
+
+/// Shown by the services that render emoji but not Slack's shortcodes.
+pub const ALL_PASSED: &str = "✅ All tests passed";
+pub const BACK_TO_GREEN: &str = "🟢 Back to green: every test passes again";
+
+/// A service that test results are posted to, rendering them in its own
+/// message format.
+pub trait Notifier {
+    /// Posts the failures of a run, or its summary when every test passed.
+    fn notify(&self, run: &TestRun, args: &MessageArgs) -> Result<()>;
+
+    /// Announces that every test passes again after a failing run.
+    fn notify_recovery(&self, run: &TestRun, args: &MessageArgs) -> Result<()>;
+
+    /// Posts the tests that fail now but not in the baseline.
+    fn notify_regressions(
+        &self,
+        run: &TestRun,
+        comparison: &Comparison,
+        args: &MessageArgs,
+    ) -> Result<()>;
+}
+
+/// Posts to Slack through an incoming webhook or the Web API.
+pub struct SlackNotifier<'a> {
+    destination: Destination,
+    client: &'a HttpClient,
+}
+
+impl<'a> SlackNotifier<'a> {
+    pub fn new(destination: Destination, client: &'a HttpClient) -> Self {
+        SlackNotifier {
+            destination,
+            client,
+        }
+    }
+}
+
+impl Notifier for SlackNotifier<'_> {
+    fn notify(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        let messages = slack::build_slack_messages(run, args);
+        slack::send_slack_messages(&messages, &self.destination, self.client)
+    }
+
+    fn notify_recovery(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        let message = slack::build_recovery_message(run, args);
+        slack::send_slack_messages(&[message], &self.destination, self.client)
+    }
+
+    fn notify_regressions(
+        &self,
+        run: &TestRun,
+        comparison: &Comparison,
+        args: &MessageArgs,
+    ) -> Result<()> {
+        let messages = slack::build_regression_messages(run, comparison, args);
+        slack::send_slack_messages(&messages, &self.destination, self.client)
+    }
+}
+
+/// A notifier for every service configured in `args`.
+pub fn notifiers<'a>(
+    args: &'a NotifierArgs,
+    client: &'a HttpClient,
+) -> Vec<Box<dyn Notifier + 'a>> {
+    let mut notifiers: Vec<Box<dyn Notifier + 'a>> = vec![];
+    if let Some(destination) = Destination::from_args(&args.slack) {
+        notifiers.push(Box::new(SlackNotifier::new(destination, client)));
+    }
+    if let Some(url) = &args.teams.teams_webhook_url {
+        notifiers.push(Box::new(TeamsNotifier::new(url.clone(), client)));
+    }
+    if let Some(url) = &args.discord.discord_webhook_url {
+        notifiers.push(Box::new(DiscordNotifier::new(url.clone(), client)));
+    }
+    let markdown = &args.markdown;
+    for (flavor, url) in [
+        (Flavor::Mattermost, &markdown.mattermost_webhook_url),
+        (Flavor::RocketChat, &markdown.rocketchat_webhook_url),
+    ] {
+        if let Some(url) = url {
+            notifiers.push(Box::new(MarkdownNotifier::new(
+                flavor,
+                url.clone(),
+                &markdown.overrides,
+                client,
+            )));
+        }
+    }
+    if let Some(url) = &args.google_chat.google_chat_webhook_url {
+        let thread_key = args.google_chat.google_chat_thread_key.as_deref();
+        notifiers.push(Box::new(GoogleChatNotifier::new(
+            url.clone(),
+            thread_key,
+            client,
+        )));
+    }
+    if args.email.smtp_host.is_some() {
+        notifiers.push(Box::new(EmailNotifier::new(
+            &args.email,
+            client.retry_policy(),
+            client.timeout(),
+        )));
+    }
+    notifiers
+}
+
+/// Calls `notify` with every notifier. A failed delivery does not stop the
+/// others; the first error is returned and any others are printed.
+pub fn notify_all(
+    notifiers: &[Box<dyn Notifier + '_>],
+    notify: impl Fn(&dyn Notifier) -> Result<()>,
+) -> Result<()> {
+    let mut result = Ok(());
+    for notifier in notifiers {
+        if let Err(err) = notify(notifier.as_ref()) {
+            if result.is_err() {
+                eprintln!("{}", err);
+            }
+            result = result.and(Err(err));
+        }
+    }
+    result
+}
+
+/// A list shown after the failures, such as the flaky tests.
+pub struct ListSection<'a> {
+    pub title: &'static str,
+    pub items: Vec<ListItem<'a>>,
+}
+
+impl ListSection<'_> {
+    /// The title with the number of items.
+    pub fn heading(&self) -> String {
+        format!("{} ({})", self.title, self.items.len())
+    }
+}
+
+/// A test in a [`ListSection`], with a note such as its quarantine ticket.
+pub struct ListItem<'a> {
+    pub name: &'a str,
+    pub note: Option<String>,
+    /// URL that the note links to.
+    pub link: Option<&'a str>,
+}
+
+impl fmt::Display for ListItem<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(note) = &self.note {
+            write!(f, " ({})", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// The flaky tests, quarantined failures and tests fixed since the previous
+/// run, leaving out the empty lists.
+pub fn list_sections(run: &TestRun) -> Vec<ListSection<'_>> {
+    let flaky = run.flaky_tests.iter().map(|flaky| ListItem {
+        name: &flaky.case.name,
+        note: Some(format!("passed after {} attempts", flaky.attempts)),
+        link: None,
+    });
+    let quarantined = run.quarantined_tests.iter().map(|quarantined| ListItem {
+        name: &quarantined.failed.case.name,
+        note: quarantined.entry.ticket_label().map(str::to_string),
+        link: quarantined.entry.ticket_url.as_deref(),
+    });
+    [
+        ListSection {
+            title: "Flaky tests",
+            items: flaky.collect(),
+        },
+        ListSection {
+            title: "Quarantined failures",
+            items: quarantined.collect(),
+        },
+        fixed_section("Fixed since last run", run),
+    ]
+    .into_iter()
+    .filter(|section| !section.items.is_empty())
+    .collect()
+}
+
+/// The tests fixed since the previous run, as listed by recovery messages.
+pub fn recovery_sections(run: &TestRun) -> Vec<ListSection<'_>> {
+    let fixed = fixed_section("Fixed tests", run);
+    if fixed.items.is_empty() {
+        vec![]
+    } else {
+        vec![fixed]
+    }
+}
+
+fn fixed_section<'a>(title: &'static str, run: &'a TestRun) -> ListSection<'a> {
+    ListSection {
+        title,
+        items: run
+            .fixed_tests
+            .iter()
+            .map(|name| ListItem {
+                name,
+                note: None,
+                link: None,
+            })
+            .collect(),
+    }
+}
+
+/// Shortens `text` to at most `max_chars` characters, closing a code block
+/// that the cut would leave open.
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(4)).collect();
+    truncated.push('…');
+    if truncated.matches("```").count() % 2 == 1 {
+        truncated.push_str("```");
+    }
+    truncated
+}
+
+/// Escapes the characters that Markdown treats as inline formatting, so that
+/// test names and messages show as written.
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes text for HTML, in element content and in quoted attributes.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_list_sections() {
+        let mut run = fixtures::test_run(1);
+        run.quarantined_tests = vec![fixtures::quarantined_test("test_muted")];
+        run.fixed_tests = vec!["test_fixed".to_string()];
+
+        let sections = list_sections(&run);
+        let listed: Vec<(String, Vec<String>)> = sections
+            .iter()
+            .map(|section| {
+                let items = section.items.iter().map(ListItem::to_string).collect();
+                (section.heading(), items)
+            })
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                (
+                    "Quarantined failures (1)".to_string(),
+                    vec!["test_muted (PAY-12)".to_string()]
+                ),
+                (
+                    "Fixed since last run (1)".to_string(),
+                    vec!["test_fixed".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(
+            sections[0].items[0].link,
+            Some("https://jira.example.com/browse/PAY-12")
+        );
+        assert_eq!(recovery_sections(&run)[0].heading(), "Fixed tests (1)");
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        assert_eq!(
+            escape_markdown(r"test_[a]*b* ~c~ `d` \e"),
+            r"test\_\[a\]\*b\* \~c\~ \`d\` \\e"
+        );
+    }
+
+    #[test]
+    fn test_truncate_closes_code_blocks() {
+        assert_eq!(truncate("short", 10), "short");
+        let truncated = truncate("title\n```line one\nline two```", 16);
+        assert_eq!(truncated, "title\n```lin…```");
+        assert!(truncated.chars().count() <= 16);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::error::{Error, Result};
+use crate::FailedTest;
+use glob::{MatchOptions, Pattern};
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Ownership rules in the `CODEOWNERS` format: a path pattern followed by
+/// its owners. When several rules match, the last one wins.
+pub struct CodeOwners {
+    rules: Vec<(Vec<Pattern>, Vec<String>)>,
+}
+
+impl CodeOwners {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|err| Error::config_file(path, err))?;
+        CodeOwners::parse(&content).map_err(|err| {
+            Error::Config(format!(
+                "Invalid CODEOWNERS file {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    fn parse(content: &str) -> std::result::Result<Self, String> {
+        let mut rules = vec![];
+        for line in content.lines() {
+            let line = line.split_once('#').map_or(line, |(rule, _)| rule);
+            let mut fields = line.split_whitespace();
+            let Some(pattern) = fields.next() else {
+                continue;
+            };
+            let patterns = path_patterns(pattern)
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|err| format!("invalid pattern {}: {}", pattern, err))?;
+            rules.push((patterns, fields.map(str::to_string).collect()));
+        }
+        Ok(CodeOwners { rules })
+    }
+
+    /// Owners of a failure, matching the rules against the test's `file`
+    /// attribute, the path of its report and its classname read as a path
+    /// (`com.example.FooTest` as `com/example/FooTest`). The last rule that
+    /// matches any of them wins.
+    pub fn owners(&self, failed: &FailedTest) -> &[String] {
+        let classname = failed.case.classname.as_deref().unwrap_or_default();
+        let candidates = [
+            failed.case.file.clone(),
+            Some(failed.source.display().to_string()),
+            Some(classname.replace('.', "/")),
+        ];
+        candidates
+            .into_iter()
+            .flatten()
+            .filter(|path| !path.is_empty())
+            .filter_map(|path| self.last_match(&path))
+            .max()
+            .map_or(&[], |index| self.rules[index].1.as_slice())
+    }
+
+    /// Index of the last rule matching `path`.
+    fn last_match(&self, path: &str) -> Option<usize> {
+        let path = path.trim_start_matches("./").trim_start_matches('/');
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.rules.iter().rposition(|(patterns, _)| {
+            patterns
+                .iter()
+                .any(|pattern| pattern.matches_with(path, options))
+        })
+    }
+}
+
+/// Glob patterns equivalent to a `CODEOWNERS` pattern: one without a leading
+/// slash matches at any depth, and one naming a directory matches everything
+/// below it.
+fn path_patterns(pattern: &str) -> Vec<String> {
+    let anchored = pattern.starts_with('/') || pattern.trim_end_matches('/').contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    let base = if anchored {
+        pattern.trim_end_matches('/').to_string()
+    } else {
+        format!("**/{}", pattern.trim_end_matches('/'))
+    };
+    if pattern.ends_with('/') {
+        vec![format!("{}/**", base)]
+    } else {
+        vec![base.clone(), format!("{}/**", base)]
+    }
+}
+
+/// Where an owner's failures are posted and who gets mentioned.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Owner {
+    /// Incoming webhook of the owner's channel.
+    pub webhook_url: Option<Url>,
+    /// Channel to post to with the bot token, when there is no webhook.
+    pub channel: Option<String>,
+    /// Slack user group ID, e.g. `S0123ABCD`, mentioned in the message.
+    pub user_group: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OwnersFile {
+    #[serde(default)]
+    owners: BTreeMap<String, Owner>,
+}
+
+/// Owners as named in `CODEOWNERS`, e.g. `@acme/payments`, mapped to their
+/// Slack settings.
+pub struct Owners(BTreeMap<String, Owner>);
+
+impl Owners {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|err| Error::config_file(path, err))?;
+        Owners::parse(&content).map_err(|err| {
+            Error::Config(format!("Invalid owners file {}: {}", path.display(), err))
+        })
+    }
+
+    fn parse(content: &str) -> std::result::Result<Self, String> {
+        let file: OwnersFile = toml::from_str(content).map_err(|err| err.to_string())?;
+        for (name, owner) in &file.owners {
+            if owner.webhook_url.is_none() && owner.channel.is_none() {
+                return Err(format!("{} needs a webhook_url or a channel", name));
+            }
+        }
+        Ok(Owners(file.owners))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Owner> {
+        self.0.get(name)
+    }
+
+    /// Thread replies are only posted through the bot token, so no owner may
+    /// be posted to through an incoming webhook when threads are used.
+    pub fn check_threads(&self) -> Result<()> {
+        match self.0.iter().find(|(_, owner)| owner.webhook_url.is_some()) {
+            Some((name, _)) => Err(Error::Config(format!(
+                "Owner {} has a webhook_url, which cannot post the thread replies of \
+                 --threaded or --overflow thread",
+                name
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Failures split by the owners they are sent to.
+#[derive(Default)]
+pub struct Routes {
+    /// Owners with their failures, in the order of `Owners`.
+    pub owned: Vec<(String, Vec<FailedTest>)>,
+    /// Failures without a configured owner.
+    pub unowned: Vec<FailedTest>,
+}
+
+/// Sends every failure to each of its configured owners, and failures
+/// without one to the default destination.
+pub fn route(failed_tests: &[FailedTest], codeowners: &CodeOwners, owners: &Owners) -> Routes {
+    let mut owned: BTreeMap<&str, Vec<FailedTest>> = BTreeMap::new();
+    let mut routes = Routes::default();
+    for failed in failed_tests {
+        let mut routed = false;
+        for name in codeowners.owners(failed) {
+            if let Some((name, _)) = owners.0.get_key_value(name) {
+                owned.entry(name).or_default().push(failed.clone());
+                routed = true;
+            }
+        }
+        if !routed {
+            routes.unowned.push(failed.clone());
+        }
+    }
+    routes.owned = owned
+        .into_iter()
+        .map(|(name, failed_tests)| (name.to_string(), failed_tests))
+        .collect();
+    routes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn failed_test(classname: &str, file: Option<&str>) -> FailedTest {
+        let mut failed = fixtures::failed_test(&format!("{}::test", classname));
+        failed.case.classname = Some(classname.to_string());
+        failed.case.file = file.map(str::to_string);
+        failed
+    }
+
+    #[test]
+    fn test_codeowners_last_matching_rule_wins() {
+        let codeowners = CodeOwners::parse(
+            "# Default owners\n\
+             *                      @acme/platform\n\
+             com/acme/payments/     @acme/payments  @alice\n\
+             /services/search/**    @acme/search\n\
+             *.py                   @acme/data # scripts\n",
+        )
+        .unwrap();
+
+        let owners = |failed: &FailedTest| codeowners.owners(failed).to_vec();
+        assert_eq!(
+            owners(&failed_test("com.acme.payments.RefundTest", None)),
+            vec!["@acme/payments", "@alice"]
+        );
+        assert_eq!(
+            owners(&failed_test(
+                "test_query",
+                Some("./services/search/tests/test_query.py")
+            )),
+            vec!["@acme/data"]
+        );
+        assert_eq!(
+            owners(&failed_test(
+                "SearchTest",
+                Some("services/search/src/SearchTest.java")
+            )),
+            vec!["@acme/search"]
+        );
+        assert_eq!(
+            owners(&failed_test("com.acme.other.OtherTest", None)),
+            vec!["@acme/platform"]
+        );
+    }
+
+    #[test]
+    fn test_route_failures_to_configured_owners() {
+        let codeowners = CodeOwners::parse(
+            "com/acme/payments/ @acme/payments @alice\ncom/acme/search/ @acme/search\n",
+        )
+        .unwrap();
+        let owners = Owners::parse(
+            r##"
+            [owners."@acme/payments"]
+            webhook_url = "https://hooks.slack.com/services/T/B/X"
+            user_group = "S0123"
+
+            [owners."@acme/search"]
+            channel = "#search-ci"
+            "##,
+        )
+        .unwrap();
+
+        let routes = route(
+            &[
+                failed_test("com.acme.payments.RefundTest", None),
+                failed_test("com.acme.search.QueryTest", None),
+                failed_test("com.acme.search.IndexTest", None),
+                failed_test("com.acme.other.OtherTest", None),
+            ],
+            &codeowners,
+            &owners,
+        );
+
+        let owned: Vec<(&str, usize)> = routes
+            .owned
+            .iter()
+            .map(|(name, failed_tests)| (name.as_str(), failed_tests.len()))
+            .collect();
+        assert_eq!(owned, vec![("@acme/payments", 1), ("@acme/search", 2)]);
+        assert_eq!(routes.unowned.len(), 1);
+        assert_eq!(
+            owners.get("@acme/payments").unwrap().user_group.as_deref(),
+            Some("S0123")
+        );
+        assert!(owners.check_threads().is_err());
+        assert!(Owners::parse("[owners.\"@acme/x\"]\nuser_group = \"S1\"").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::date::{local_date, today};
+use crate::error::{Error, Result};
+use glob::Pattern;
+use junit_parser::TestCase;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use toml::value::{Date, Datetime};
+
+/// Layout of the quarantine file:
+///
+/// ```toml
+/// [[quarantine]]
+/// classname = "com.example.PaymentTest"
+/// name = "refund*"
+/// ticket = "PAY-12"
+/// ticket_url = "https://jira.example.com/browse/PAY-12"
+/// expires = 2026-12-31
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuarantineFile {
+    #[serde(default)]
+    quarantine: Vec<RawEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEntry {
+    classname: String,
+    #[serde(default = "any_name")]
+    name: String,
+    ticket: Option<String>,
+    ticket_url: Option<String>,
+    expires: Option<Datetime>,
+}
+
+fn any_name() -> String {
+    "*".to_string()
+}
+
+/// Known-broken tests whose failures are reported without alerting anyone.
+#[derive(Debug)]
+pub struct Quarantine {
+    entries: Vec<QuarantineEntry>,
+    today: Date,
+}
+
+/// Test cases matched by a `classname` and `name` glob pattern.
+#[derive(Debug, Clone)]
+pub struct QuarantineEntry {
+    pub classname: Pattern,
+    pub name: Pattern,
+    pub ticket: Option<String>,
+    /// Link to the ticket, shown on its label.
+    pub ticket_url: Option<String>,
+    /// Last day on which the entry mutes failures.
+    pub expires: Option<Date>,
+}
+
+impl QuarantineEntry {
+    fn matches(&self, case: &TestCase) -> bool {
+        self.classname
+            .matches(case.classname.as_deref().unwrap_or_default())
+            && self.name.matches(&case.original_name)
+    }
+
+    fn is_expired(&self, today: Date) -> bool {
+        self.expires.is_some_and(|expires| expires < today)
+    }
+
+    /// The ticket label, or its URL when it has none.
+    pub fn ticket_label(&self) -> Option<&str> {
+        self.ticket.as_deref().or(self.ticket_url.as_deref())
+    }
+}
+
+impl fmt::Display for QuarantineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}", self.classname, self.name)
+    }
+}
+
+impl Quarantine {
+    /// Reads the quarantine file, or returns an empty quarantine without one.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Quarantine {
+                entries: vec![],
+                today: today(),
+            });
+        };
+        let content = fs::read_to_string(path).map_err(|err| Error::config_file(path, err))?;
+        Quarantine::parse(&content, today()).map_err(|err| {
+            Error::Config(format!(
+                "Invalid quarantine file {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    fn parse(content: &str, today: Date) -> std::result::Result<Self, String> {
+        let file: QuarantineFile = toml::from_str(content).map_err(|err| err.to_string())?;
+        let entries = file
+            .quarantine
+            .into_iter()
+            .map(|raw| {
+                let pattern = |pattern: &str| {
+                    Pattern::new(pattern)
+                        .map_err(|err| format!("invalid pattern {}: {}", pattern, err))
+                };
+                let expires = raw
+                    .expires
+                    .map(local_date)
+                    .transpose()
+                    .map_err(|err| format!("invalid expires: {}", err))?;
+                Ok(QuarantineEntry {
+                    classname: pattern(&raw.classname)?,
+                    name: pattern(&raw.name)?,
+                    ticket: raw.ticket,
+                    ticket_url: raw.ticket_url,
+                    expires,
+                })
+            })
+            .collect::<std::result::Result<_, String>>()?;
+        Ok(Quarantine { entries, today })
+    }
+
+    /// The first unexpired entry matching `case`.
+    pub fn find(&self, case: &TestCase) -> Option<&QuarantineEntry> {
+        self.entries
+            .iter()
+            .find(|entry| !entry.is_expired(self.today) && entry.matches(case))
+    }
+
+    /// Entries past their expiry date, which no longer mute failures.
+    pub fn expired(&self) -> Vec<QuarantineEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_expired(self.today))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: u16, month: u8, day: u8) -> Date {
+        Date { year, month, day }
+    }
+
+    fn case(classname: &str, name: &str) -> TestCase {
+        TestCase {
+            name: format!("{}::{}", classname, name),
+            original_name: name.to_string(),
+            classname: Some(classname.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_quarantine_matches_unexpired_entries() {
+        let quarantine = Quarantine::parse(
+            r#"
+            [[quarantine]]
+            classname = "com.example.PaymentTest"
+            name = "refund*"
+            ticket = "PAY-12"
+            ticket_url = "https://jira.example.com/browse/PAY-12"
+            expires = 2026-06-30
+
+            [[quarantine]]
+            classname = "com.example.legacy.*"
+            expires = 2026-06-01
+            "#,
+            date(2026, 6, 15),
+        )
+        .unwrap();
+
+        let entry = quarantine
+            .find(&case("com.example.PaymentTest", "refund_twice"))
+            .unwrap();
+        assert_eq!(entry.ticket_label(), Some("PAY-12"));
+        assert_eq!(
+            entry.ticket_url.as_deref(),
+            Some("https://jira.example.com/browse/PAY-12")
+        );
+        assert!(quarantine
+            .find(&case("com.example.PaymentTest", "charge"))
+            .is_none());
+        assert!(quarantine
+            .find(&case("com.example.legacy.Old", "test"))
+            .is_none());
+
+        let expired = quarantine.expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].to_string(), "com.example.legacy.*::*");
+        assert_eq!(expired[0].expires, Some(date(2026, 6, 1)));
+    }
+
+    #[test]
+    fn test_quarantine_rejects_invalid_entries() {
+        let today = date(2026, 1, 1);
+        assert!(Quarantine::parse("[[quarantine]]\nclassname = \"[\"", today).is_err());
+        assert!(Quarantine::parse("[[quarantine]]\nname = \"a\"", today).is_err());
+        assert!(Quarantine::parse(
+            "[[quarantine]]\nclassname = \"a\"\nexpires = 2026-01-01T10:00:00",
+            today
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_load_reports_unreadable_file_as_config_error() {
+        let err = Quarantine::load(Some(Path::new("missing-quarantine.toml"))).unwrap_err();
+        assert!(matches!(err, Error::Config(_)));
+        assert_eq!(err.exit_code(), std::process::ExitCode::from(2));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::cli::DeliveryArgs;
+use crate::error::{Error, Result};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Upper bound for a single backoff delay, before jitter.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The outcome of one delivery attempt, as seen by [`RetryPolicy::retry`].
+pub enum Attempt<T> {
+    Done(T),
+    /// A transient failure, retried after the delay the server asked for or
+    /// after the backoff.
+    Retry(Error, Option<Duration>),
+    Fail(Error),
+}
+
+/// How deliveries retry transient failures, whatever their transport: with
+/// jittered exponential backoff, until `--retries` or `--deadline-secs` is
+/// exhausted.
+pub struct RetryPolicy {
+    retries: u32,
+    retry_delay: Duration,
+    deadline: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(args: &DeliveryArgs) -> Self {
+        RetryPolicy {
+            retries: args.retries,
+            retry_delay: Duration::from_millis(args.retry_delay_ms),
+            deadline: Duration::from_secs(args.deadline_secs),
+        }
+    }
+
+    /// Calls `attempt` until it is done or fails for good, retrying transient
+    /// failures while the policy allows.
+    pub fn retry<T>(&self, mut attempt: impl FnMut() -> Attempt<T>) -> Result<T> {
+        let started = Instant::now();
+        let mut attempts = 0;
+        loop {
+            let (error, retry_after) = match attempt() {
+                Attempt::Done(value) => return Ok(value),
+                Attempt::Retry(error, retry_after) => (error, retry_after),
+                Attempt::Fail(error) => return Err(error),
+            };
+
+            let delay = retry_after.unwrap_or_else(|| self.backoff(attempts));
+            if attempts >= self.retries || started.elapsed() + delay > self.deadline {
+                return Err(error);
+            }
+            eprintln!("{}; retrying in {:.1}s", error, delay.as_secs_f64());
+            thread::sleep(delay);
+            attempts += 1;
+        }
+    }
+
+    /// Exponential backoff with "equal jitter": half of the delay is fixed, the
+    /// other half random, so concurrent pipelines do not retry in lockstep.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .retry_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_BACKOFF);
+        let half = delay / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn test_policy() -> RetryPolicy {
+        RetryPolicy {
+            retries: 2,
+            retry_delay: Duration::from_millis(1),
+            deadline: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_retry_stops_at_retries_or_permanent_failure() {
+        let mut attempts = 0;
+        let err = test_policy()
+            .retry(|| -> Attempt<()> {
+                attempts += 1;
+                Attempt::Retry(Error::delivery("busy"), None)
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "busy");
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        assert!(test_policy()
+            .retry(|| -> Attempt<()> {
+                attempts += 1;
+                Attempt::Fail(Error::delivery("rejected"))
+            })
+            .is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially_with_jitter() {
+        let policy = RetryPolicy {
+            retry_delay: Duration::from_secs(1),
+            ..test_policy()
+        };
+        for _ in 0..100 {
+            let delay = policy.backoff(3);
+            assert!(delay >= Duration::from_secs(4) && delay <= Duration::from_secs(8));
+        }
+        assert!(policy.backoff(20) <= MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,1455 @@
+use crate::cli::{GroupBy, Layout, MessageArgs, Overflow, SlackArgs};
+use crate::cluster::{self, Cluster};
+use crate::diff::Comparison;
+use crate::error::{Error, Result};
+use crate::http::HttpClient;
+use crate::known_issues::KnownIssue;
+use crate::notifier::truncate;
+use crate::ownership::Owner;
+use crate::quarantine::QuarantineEntry;
+use crate::summary::{format_duration, format_pass_rate, Summary};
+use crate::{FailedTest, FlakyTest, QuarantinedTest, TestRun};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+const ALL_PASSED: &str = ":white_check_mark: All tests passed";
+const BACK_TO_GREEN: &str = ":large_green_circle: Back to green: every test passes again";
+const DETAILS_IN_THREAD: &str = ":thread: Failure details in the thread";
+
+/// Slack truncates message text beyond this many characters.
+const MAX_TEXT_LENGTH: usize = 40_000;
+/// Slack rejects messages with more blocks than this.
+const MAX_BLOCKS: usize = 50;
+const MAX_HEADER_LENGTH: usize = 150;
+const MAX_SECTION_LENGTH: usize = 3_000;
+/// Each failure takes two blocks; the rest is reserved for the header,
+/// summary, divider, the "…and N more" line, the known issue, flaky,
+/// quarantined and fixed test sections, the comparison with a baseline and
+/// the mentions.
+const MAX_FAILURES_PER_MESSAGE: usize = (MAX_BLOCKS - 15) / 2;
+/// Number of test names shown for each cluster of failures.
+const MAX_CLUSTER_SAMPLE: usize = 5;
+/// Longest failure message quoted for a flaky test.
+const MAX_FLAKY_MESSAGE_LENGTH: usize = 200;
+
+/// Payload accepted by Slack incoming webhooks.
+///
+/// `text` is always set: Slack uses it for notifications and for clients that
+/// cannot render `blocks`.
+#[derive(Serialize)]
+pub struct SlackMessage {
+    pub text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<Block>,
+    /// Post as a reply in the thread of the first message.
+    #[serde(skip)]
+    pub in_thread: bool,
+}
+
+/// A Block Kit layout block.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Header {
+        text: Text,
+    },
+    Section {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<Text>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        fields: Vec<Text>,
+    },
+    Context {
+        elements: Vec<Text>,
+    },
+    Divider,
+}
+
+/// A Block Kit text object.
+#[derive(Serialize)]
+#[serde(tag = "type", content = "text")]
+pub enum Text {
+    #[serde(rename = "plain_text")]
+    Plain(String),
+    #[serde(rename = "mrkdwn")]
+    Markdown(String),
+}
+
+/// Builds the messages to post, keeping each one within Slack's size limits.
+///
+/// The first message carries the summary and at most `--max-failures`
+/// failures. The remaining failures are either counted in an "…and N more"
+/// line or listed in further messages or thread replies, depending on
+/// `--overflow`. With `--threaded` every failure goes to the thread instead.
+/// With `--layout clusters` failures sharing an error signature are shown
+/// once, in a single message. With `--group-by` failures are listed by group,
+/// each group under a heading with its number of failures.
+///
+/// Failures grouped by known issue, flaky tests, quarantined failures and
+/// tests fixed since the previous run are listed at the end of the first
+/// message.
+pub fn build_slack_messages(run: &TestRun, args: &MessageArgs) -> Vec<SlackMessage> {
+    let groups = Groups::new(&run.failed_tests, args.group_by);
+    let failed_tests = groups.sort(&run.failed_tests);
+    let mut messages = match args.layout {
+        Layout::Clusters => vec![build_cluster_message(&run.failed_tests, &run.summary, args)],
+        Layout::List if args.threaded => {
+            build_threaded_messages(&failed_tests, &run.summary, &groups, args)
+        }
+        Layout::List => build_flat_messages(&failed_tests, &run.summary, &groups, args),
+    };
+    append_known_issue_section(&mut messages[0], &run.failed_tests);
+    append_flaky_section(&mut messages[0], &run.flaky_tests, args);
+    append_quarantine_section(
+        &mut messages[0],
+        &run.quarantined_tests,
+        &run.expired_quarantine,
+        args,
+    );
+    append_fixed_section(&mut messages[0], &run.fixed_tests, args);
+    append_mentions(&mut messages[0], &run.mentions);
+    messages
+}
+
+/// Builds the messages for the tests that fail now but not in the baseline,
+/// followed by the counts of every kind of change.
+pub fn build_regression_messages(
+    run: &TestRun,
+    comparison: &Comparison,
+    args: &MessageArgs,
+) -> Vec<SlackMessage> {
+    let mut messages = build_slack_messages(run, args);
+    append_section(
+        &mut messages[0],
+        &format!("*Compared with the baseline*\n{}\n", comparison.counts()),
+    );
+    messages
+}
+
+/// Announces that the tests pass again after a failing run, naming the
+/// tests that were fixed.
+pub fn build_recovery_message(run: &TestRun, args: &MessageArgs) -> SlackMessage {
+    let mut message = SlackMessage {
+        text: format!(
+            "*{}*\n{}\n\n{}\n",
+            escape(&args.title),
+            run.summary,
+            BACK_TO_GREEN
+        ),
+        blocks: vec![
+            header_block(&args.title),
+            summary_block(&run.summary),
+            Block::Section {
+                text: Some(Text::Markdown(BACK_TO_GREEN.to_string())),
+                fields: vec![],
+            },
+        ],
+        in_thread: false,
+    };
+    append_flaky_section(&mut message, &run.flaky_tests, args);
+    append_quarantine_section(
+        &mut message,
+        &run.quarantined_tests,
+        &run.expired_quarantine,
+        args,
+    );
+    append_fixed_section(&mut message, &run.fixed_tests, args);
+    message
+}
+
+/// Section headings for failures listed by group with `--group-by`.
+struct Groups {
+    group_by: GroupBy,
+    /// Number of failures in each group.
+    counts: HashMap<String, usize>,
+}
+
+impl Groups {
+    fn new(failed_tests: &[FailedTest], group_by: GroupBy) -> Self {
+        let mut counts = HashMap::new();
+        for group in failed_tests
+            .iter()
+            .filter_map(|failed| failed.group(group_by))
+        {
+            *counts.entry(group).or_default() += 1;
+        }
+        Groups { group_by, counts }
+    }
+
+    /// Orders failures by group, keeping the groups in the order of their
+    /// first failure.
+    fn sort<'a>(&self, failed_tests: &'a [FailedTest]) -> Cow<'a, [FailedTest]> {
+        if self.group_by == GroupBy::None {
+            return Cow::Borrowed(failed_tests);
+        }
+        let mut order: Vec<String> = vec![];
+        for failed in failed_tests {
+            let group = failed.group(self.group_by).unwrap_or_default();
+            if !order.contains(&group) {
+                order.push(group);
+            }
+        }
+        let mut sorted = failed_tests.to_vec();
+        sorted.sort_by_key(|failed| {
+            let group = failed.group(self.group_by).unwrap_or_default();
+            order.iter().position(|other| *other == group)
+        });
+        Cow::Owned(sorted)
+    }
+
+    /// The heading shown above `failed_tests[index]` when it starts a group.
+    fn heading(&self, failed_tests: &[FailedTest], index: usize) -> Option<String> {
+        let group = failed_tests[index].group(self.group_by)?;
+        if index > 0 && failed_tests[index - 1].group(self.group_by).as_ref() == Some(&group) {
+            return None;
+        }
+        let count = self.counts.get(&group).copied().unwrap_or_default();
+        Some(format!("*{}* ({} failed)", escape(&group), count))
+    }
+}
+
+impl Default for Groups {
+    fn default() -> Self {
+        Groups::new(&[], GroupBy::None)
+    }
+}
+
+fn build_flat_messages(
+    failed_tests: &[FailedTest],
+    summary: &Summary,
+    groups: &Groups,
+    args: &MessageArgs,
+) -> Vec<SlackMessage> {
+    let shown = failed_tests
+        .len()
+        .min(args.max_failures)
+        .min(MAX_FAILURES_PER_MESSAGE);
+    let (first, rest) = failed_tests.split_at(shown);
+    let mut message = build_slack_message(first, summary, groups, args);
+    if rest.is_empty() {
+        return vec![message];
+    }
+
+    let more = match args.overflow {
+        Overflow::Truncate => format!("…and {} more", rest.len()),
+        Overflow::FollowUp => format!("…and {} more in the following messages", rest.len()),
+        Overflow::Thread => format!("…and {} more in the thread", rest.len()),
+    };
+    // The notice must survive truncation, so only the listed failures are cut.
+    message.text = truncate(&message.text, MAX_TEXT_LENGTH - more.chars().count()) + &more;
+    message.blocks.push(Block::Context {
+        elements: vec![Text::Markdown(more)],
+    });
+
+    let mut messages = vec![message];
+    match args.overflow {
+        Overflow::Truncate => {}
+        Overflow::FollowUp => messages.extend(build_continuation_messages(
+            rest,
+            |part, parts| format!("{} (continued {}/{})", args.title, part, parts),
+            false,
+            groups,
+            args,
+        )),
+        Overflow::Thread => messages.extend(build_continuation_messages(
+            rest,
+            |part, parts| format!("{} (continued {}/{})", args.title, part, parts),
+            true,
+            groups,
+            args,
+        )),
+    }
+    messages
+}
+
+/// Shows each cluster of failures once: the details of its first failure,
+/// the number of tests and a sample of their names.
+fn build_cluster_message(
+    failed_tests: &[FailedTest],
+    summary: &Summary,
+    args: &MessageArgs,
+) -> SlackMessage {
+    let mut message = build_slack_message(&[], summary, &Groups::default(), args);
+    let clusters = cluster::cluster_failures(failed_tests);
+    if clusters.is_empty() {
+        return message;
+    }
+
+    let shown = clusters
+        .len()
+        .min(args.max_failures)
+        .min(MAX_FAILURES_PER_MESSAGE);
+    message.blocks.push(Block::Divider);
+    for cluster in &clusters[..shown] {
+        let (heading, sample) = describe_cluster(cluster);
+        let description = describe_failure(cluster.tests[0], args.stack_trace_lines);
+        let mut text = heading;
+        if !description.is_empty() {
+            text.push('\n');
+            text.push_str(&description);
+        }
+        message.text.push_str(&format!("- {}\n{}\n", text, sample));
+        message.blocks.extend([
+            Block::Section {
+                text: Some(Text::Markdown(truncate(&text, MAX_SECTION_LENGTH))),
+                fields: vec![],
+            },
+            Block::Context {
+                elements: vec![Text::Markdown(truncate(&sample, MAX_SECTION_LENGTH))],
+            },
+        ]);
+    }
+    if shown < clusters.len() {
+        let more = format!("…and {} more clusters", clusters.len() - shown);
+        message.text.push_str(&more);
+        message.blocks.push(Block::Context {
+            elements: vec![Text::Markdown(more)],
+        });
+    }
+    message.text = truncate(&message.text, MAX_TEXT_LENGTH);
+    message
+}
+
+/// A heading with the size of the cluster, and the names of some of its tests.
+fn describe_cluster(cluster: &Cluster) -> (String, String) {
+    let count = cluster.tests.len();
+    let heading = if count == 1 {
+        "*1 test*".to_string()
+    } else {
+        format!("*{} tests* with the same error", count)
+    };
+    let mut sample: Vec<String> = cluster
+        .tests
+        .iter()
+        .take(MAX_CLUSTER_SAMPLE)
+        .map(|failed| escape(&failed.case.name))
+        .collect();
+    if count > MAX_CLUSTER_SAMPLE {
+        sample.push(format!("…and {} more", count - MAX_CLUSTER_SAMPLE));
+    }
+    (heading, sample.join(", "))
+}
+
+/// A compact summary naming the failed tests, with the details as thread replies.
+fn build_threaded_messages(
+    failed_tests: &[FailedTest],
+    summary: &Summary,
+    groups: &Groups,
+    args: &MessageArgs,
+) -> Vec<SlackMessage> {
+    let mut parent = build_slack_message(&[], summary, groups, args);
+    if failed_tests.is_empty() {
+        return vec![parent];
+    }
+
+    let shown = failed_tests.len().min(args.max_failures);
+    let mut names: String = failed_tests[..shown]
+        .iter()
+        .map(|failed| format!("• {}{}\n", escape(&failed.case.name), history_badge(failed)))
+        .collect();
+    if shown < failed_tests.len() {
+        names.push_str(&format!("…and {} more\n", failed_tests.len() - shown));
+    }
+    parent.text = truncate(
+        &format!("{}{}{}", parent.text, names, DETAILS_IN_THREAD),
+        MAX_TEXT_LENGTH,
+    );
+    parent.blocks.extend([
+        Block::Divider,
+        Block::Section {
+            text: Some(Text::Markdown(truncate(&names, MAX_SECTION_LENGTH))),
+            fields: vec![],
+        },
+        Block::Context {
+            elements: vec![Text::Markdown(DETAILS_IN_THREAD.to_string())],
+        },
+    ]);
+
+    let mut messages = vec![parent];
+    messages.extend(build_continuation_messages(
+        failed_tests,
+        |part, parts| format!("Failure details ({}/{})", part, parts),
+        true,
+        groups,
+        args,
+    ));
+    messages
+}
+
+/// Groups the failures by the known issue they were attributed to, if any
+/// were.
+fn append_known_issue_section(message: &mut SlackMessage, failed_tests: &[FailedTest]) {
+    if failed_tests
+        .iter()
+        .all(|failed| failed.known_issue.is_none())
+    {
+        return;
+    }
+    let mut groups: Vec<(Option<&KnownIssue>, Vec<&str>)> = vec![];
+    for failed in failed_tests {
+        let issue = failed.known_issue.as_ref();
+        let name = failed.case.name.as_str();
+        match groups.iter_mut().find(|(group, _)| *group == issue) {
+            Some((_, names)) => names.push(name),
+            None => groups.push((issue, vec![name])),
+        }
+    }
+    // Failures without a known issue need attention first.
+    groups.sort_by_key(|(issue, _)| issue.is_some());
+
+    let mut section = "*Failures by known issue*\n".to_string();
+    for (issue, names) in groups {
+        let label = issue.map_or("No known issue".to_string(), format_known_issue);
+        let names: Vec<String> = names.iter().map(|name| escape(name)).collect();
+        section.push_str(&format!(
+            "• {} ({}): {}\n",
+            label,
+            names.len(),
+            names.join(", ")
+        ));
+    }
+    append_section(message, &section);
+}
+
+/// Links the issue's label to its URL, if it has one.
+fn format_known_issue(issue: &KnownIssue) -> String {
+    match &issue.url {
+        Some(url) => format!("<{}|{}>", escape(url), escape(&issue.label)),
+        None => escape(&issue.label),
+    }
+}
+
+/// Lists tests that only passed after being rerun, with their first failure.
+fn append_flaky_section(message: &mut SlackMessage, flaky_tests: &[FlakyTest], args: &MessageArgs) {
+    if flaky_tests.is_empty() {
+        return;
+    }
+    let shown = flaky_tests.len().min(args.max_failures);
+    let mut section = format!("*Flaky tests ({})*\n", flaky_tests.len());
+    for flaky in &flaky_tests[..shown] {
+        section.push_str(&format!(
+            "• *{}* passed on attempt {}",
+            escape(&flaky.case.name),
+            flaky.attempts
+        ));
+        let failure = &flaky.first_failure;
+        let mut details = vec![];
+        if !failure.rerun_type.is_empty() {
+            details.push(format!("`{}`", escape(&failure.rerun_type)));
+        }
+        let first_line = failure.message.lines().next().unwrap_or_default().trim();
+        if !first_line.is_empty() {
+            details.push(escape(&truncate(first_line, MAX_FLAKY_MESSAGE_LENGTH)));
+        }
+        if !details.is_empty() {
+            section.push_str(&format!(", first failure: {}", details.join(": ")));
+        }
+        section.push('\n');
+    }
+    if shown < flaky_tests.len() {
+        section.push_str(&format!("…and {} more\n", flaky_tests.len() - shown));
+    }
+    append_section(message, &section);
+}
+
+/// Lists muted failures of quarantined tests with their tickets, and flags
+/// quarantine entries that have expired.
+fn append_quarantine_section(
+    message: &mut SlackMessage,
+    quarantined_tests: &[QuarantinedTest],
+    expired: &[QuarantineEntry],
+    args: &MessageArgs,
+) {
+    if quarantined_tests.is_empty() && expired.is_empty() {
+        return;
+    }
+    let mut section = String::new();
+    if !quarantined_tests.is_empty() {
+        let shown = quarantined_tests.len().min(args.max_failures);
+        section.push_str(&format!(
+            "*Quarantined failures ({})*\n",
+            quarantined_tests.len()
+        ));
+        for quarantined in &quarantined_tests[..shown] {
+            section.push_str(&format!("• {}", escape(&quarantined.failed.case.name)));
+            if let Some(ticket) = format_ticket(&quarantined.entry) {
+                section.push_str(&format!(" ({})", ticket));
+            }
+            section.push('\n');
+        }
+        if shown < quarantined_tests.len() {
+            section.push_str(&format!("…and {} more\n", quarantined_tests.len() - shown));
+        }
+    }
+    for entry in expired {
+        section.push_str(&format!(
+            ":warning: Quarantine of `{}` expired on {}",
+            escape(&entry.to_string()),
+            entry.expires.map_or(String::new(), |date| date.to_string())
+        ));
+        if let Some(ticket) = format_ticket(entry) {
+            section.push_str(&format!(" ({})", ticket));
+        }
+        section.push('\n');
+    }
+    append_section(message, &section);
+}
+
+/// Links the entry's ticket to its URL, if it has one.
+fn format_ticket(entry: &QuarantineEntry) -> Option<String> {
+    let label = escape(entry.ticket_label()?);
+    Some(match &entry.ticket_url {
+        Some(url) => format!("<{}|{}>", escape(url), label),
+        None => label,
+    })
+}
+
+/// Lists tests that failed in the previous run and pass now.
+fn append_fixed_section(message: &mut SlackMessage, fixed_tests: &[String], args: &MessageArgs) {
+    if fixed_tests.is_empty() {
+        return;
+    }
+    let shown = fixed_tests.len().min(args.max_failures);
+    let mut section = format!("*Fixed since last run ({})*\n", fixed_tests.len());
+    for name in &fixed_tests[..shown] {
+        section.push_str(&format!("• {}\n", escape(name)));
+    }
+    if shown < fixed_tests.len() {
+        section.push_str(&format!("…and {} more\n", fixed_tests.len() - shown));
+    }
+    append_section(message, &section);
+}
+
+/// Mentions users or user groups at the end of `message`, so that they are
+/// notified. `mentions` are in Slack's syntax, e.g. `<!subteam^S0123ABCD>`.
+fn append_mentions(message: &mut SlackMessage, mentions: &[String]) {
+    if mentions.is_empty() {
+        return;
+    }
+    let line = format!("cc {}", mentions.join(" "));
+    message.text = truncate(&format!("{}\n{}", message.text, line), MAX_TEXT_LENGTH);
+    message.blocks.push(Block::Context {
+        elements: vec![Text::Markdown(line)],
+    });
+}
+
+pub fn user_mention(id: &str) -> String {
+    format!("<@{}>", id)
+}
+
+pub fn user_group_mention(id: &str) -> String {
+    format!("<!subteam^{}>", id)
+}
+
+/// Adds a section below a divider at the end of `message`.
+fn append_section(message: &mut SlackMessage, section: &str) {
+    message.text = truncate(&format!("{}\n{}", message.text, section), MAX_TEXT_LENGTH);
+    message.blocks.extend([
+        Block::Divider,
+        Block::Section {
+            text: Some(Text::Markdown(truncate(section, MAX_SECTION_LENGTH))),
+            fields: vec![],
+        },
+    ]);
+}
+
+fn build_slack_message(
+    failed_tests: &[FailedTest],
+    summary: &Summary,
+    groups: &Groups,
+    args: &MessageArgs,
+) -> SlackMessage {
+    SlackMessage {
+        text: truncate(
+            &format_slack_message(failed_tests, summary, groups, args),
+            MAX_TEXT_LENGTH,
+        ),
+        blocks: format_slack_blocks(failed_tests, summary, groups, args),
+        in_thread: false,
+    }
+}
+
+/// Splits failures into messages posted after the first one, titled by
+/// `title(part, parts)`.
+fn build_continuation_messages(
+    failed_tests: &[FailedTest],
+    title: impl Fn(usize, usize) -> String,
+    in_thread: bool,
+    groups: &Groups,
+    args: &MessageArgs,
+) -> Vec<SlackMessage> {
+    let chunks: Vec<_> = failed_tests.chunks(MAX_FAILURES_PER_MESSAGE).collect();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let title = title(index + 1, chunks.len());
+            let mut message = build_continuation_message(chunk, &title, groups, args);
+            message.in_thread = in_thread;
+            message
+        })
+        .collect()
+}
+
+/// A message listing only failures, posted after the first one.
+fn build_continuation_message(
+    failed_tests: &[FailedTest],
+    title: &str,
+    groups: &Groups,
+    args: &MessageArgs,
+) -> SlackMessage {
+    let mut text = format!("*{}*\n\n", escape(title));
+    let mut blocks = vec![header_block(title)];
+    for (index, failed) in failed_tests.iter().enumerate() {
+        let heading = groups.heading(failed_tests, index);
+        append_heading(&mut text, heading.as_deref());
+        append_case_info(&mut text, failed, args.stack_trace_lines);
+        blocks.extend(failure_blocks(
+            failed,
+            heading.as_deref(),
+            args.stack_trace_lines,
+        ));
+    }
+    SlackMessage {
+        text: truncate(&text, MAX_TEXT_LENGTH),
+        blocks,
+        in_thread: false,
+    }
+}
+
+fn format_slack_message(
+    failed_tests: &[FailedTest],
+    summary: &Summary,
+    groups: &Groups,
+    args: &MessageArgs,
+) -> String {
+    let mut message = format!("*{}*\n{}\n\n", escape(&args.title), summary);
+    if summary.all_passed() {
+        message.push_str(ALL_PASSED);
+        message.push('\n');
+    }
+    for (index, failed) in failed_tests.iter().enumerate() {
+        append_heading(&mut message, groups.heading(failed_tests, index).as_deref());
+        append_case_info(&mut message, failed, args.stack_trace_lines);
+    }
+    message
+}
+
+fn append_heading(message: &mut String, heading: Option<&str>) {
+    if let Some(heading) = heading {
+        message.push_str(heading);
+        message.push('\n');
+    }
+}
+
+fn append_case_info(message: &mut String, failed: &FailedTest, stack_trace_lines: usize) {
+    message.push_str(&format!(
+        "- *{}*{} (`{}`)\n",
+        escape(&failed.case.name),
+        history_badge(failed),
+        failed.source.display()
+    ));
+    let description = describe_failure(failed, stack_trace_lines);
+    if !description.is_empty() {
+        message.push_str(&description);
+        message.push('\n');
+    }
+    if let Some(issue) = &failed.known_issue {
+        message.push_str(&format!("Known issue: {}\n", format_known_issue(issue)));
+    }
+}
+
+/// Marks a failure as new or still failing, when a history file is used.
+fn history_badge(failed: &FailedTest) -> String {
+    failed
+        .history
+        .map_or(String::new(), |status| format!(" `{}`", status))
+}
+
+/// Describes why a test failed: its type and message, followed by the first
+/// `stack_trace_lines` lines of the stack trace in a code block.
+fn describe_failure(failed: &FailedTest, stack_trace_lines: usize) -> String {
+    let Some(details) = failed.details() else {
+        return String::new();
+    };
+    let failure_type = details.failure_type.trim();
+    let message = details.message.trim();
+    let mut description = match (failure_type.is_empty(), message.is_empty()) {
+        (false, false) => format!("`{}`: {}", escape(failure_type), escape(message)),
+        (false, true) => format!("`{}`", escape(failure_type)),
+        (true, false) => escape(message),
+        (true, true) => details.kind.to_string(),
+    };
+
+    let stack_trace = failed.stack_trace(stack_trace_lines);
+    if !stack_trace.is_empty() {
+        description.push_str(&format!("\n```{}```", escape(&stack_trace.join("\n"))));
+    }
+    description
+}
+
+/// Escapes the characters Slack treats as control sequences in `mrkdwn`.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn format_slack_blocks(
+    failed_tests: &[FailedTest],
+    summary: &Summary,
+    groups: &Groups,
+    args: &MessageArgs,
+) -> Vec<Block> {
+    let mut blocks = vec![header_block(&args.title), summary_block(summary)];
+    if summary.all_passed() {
+        blocks.push(Block::Section {
+            text: Some(Text::Markdown(ALL_PASSED.to_string())),
+            fields: vec![],
+        });
+    }
+    if !failed_tests.is_empty() {
+        blocks.push(Block::Divider);
+    }
+    for (index, failed) in failed_tests.iter().enumerate() {
+        let heading = groups.heading(failed_tests, index);
+        blocks.extend(failure_blocks(
+            failed,
+            heading.as_deref(),
+            args.stack_trace_lines,
+        ));
+    }
+    blocks
+}
+
+/// The test counts as a grid of fields.
+fn summary_block(summary: &Summary) -> Block {
+    Block::Section {
+        text: None,
+        fields: vec![
+            Text::Markdown(format!("*Total:* {}", summary.total)),
+            Text::Markdown(format!("*Passed:* {}", summary.passed)),
+            Text::Markdown(format!("*Failed:* {}", summary.failed)),
+            Text::Markdown(format!("*Errored:* {}", summary.errored)),
+            Text::Markdown(format!("*Skipped:* {}", summary.skipped)),
+            Text::Markdown(format!(
+                "*Pass rate:* {}",
+                summary
+                    .pass_rate()
+                    .map_or("n/a".to_string(), format_pass_rate)
+            )),
+            Text::Markdown(format!("*Duration:* {}", format_duration(summary.duration))),
+        ],
+    }
+}
+
+fn header_block(title: &str) -> Block {
+    Block::Header {
+        text: Text::Plain(truncate(title, MAX_HEADER_LENGTH)),
+    }
+}
+
+/// A section describing the failure, below the heading of its group if it
+/// starts one, followed by a context line naming its report.
+fn failure_blocks(
+    failed: &FailedTest,
+    heading: Option<&str>,
+    stack_trace_lines: usize,
+) -> [Block; 2] {
+    let mut text = heading.map_or(String::new(), |heading| format!("{}\n\n", heading));
+    text.push_str(&format!(
+        "*{}*{}",
+        escape(&failed.case.name),
+        history_badge(failed)
+    ));
+    let description = describe_failure(failed, stack_trace_lines);
+    if !description.is_empty() {
+        text.push('\n');
+        text.push_str(&description);
+    }
+    if let Some(issue) = &failed.known_issue {
+        text.push_str(&format!("\nKnown issue: {}", format_known_issue(issue)));
+    }
+    [
+        Block::Section {
+            text: Some(Text::Markdown(truncate(&text, MAX_SECTION_LENGTH))),
+            fields: vec![],
+        },
+        Block::Context {
+            elements: vec![Text::Markdown(format!("`{}`", failed.source.display()))],
+        },
+    ]
+}
+
+/// Where Slack messages are posted.
+pub enum Destination {
+    /// An incoming webhook, bound to a single channel.
+    Webhook(Url),
+    /// `chat.postMessage` with a bot token, which reports back the posted message.
+    WebApi {
+        api_url: Url,
+        token: String,
+        channel: String,
+    },
+}
+
+impl Destination {
+    pub fn from_args(args: &SlackArgs) -> Option<Self> {
+        if let Some(url) = &args.webhook_url {
+            return Some(Destination::Webhook(url.clone()));
+        }
+        Some(Destination::WebApi {
+            api_url: args.slack_api_url.clone(),
+            token: args.bot_token.clone()?,
+            channel: args.channel.clone()?,
+        })
+    }
+
+    /// Where an owner's failures are posted: its own webhook, or its channel
+    /// through the bot token.
+    pub fn for_owner(name: &str, owner: &Owner, args: &SlackArgs) -> Result<Self> {
+        if let Some(url) = &owner.webhook_url {
+            return Ok(Destination::Webhook(url.clone()));
+        }
+        match (&owner.channel, &args.bot_token) {
+            (Some(channel), Some(token)) => Ok(Destination::WebApi {
+                api_url: args.slack_api_url.clone(),
+                token: token.clone(),
+                channel: channel.clone(),
+            }),
+            _ => Err(Error::Config(format!(
+                "Owner {} has no webhook_url, and posting to its channel needs --bot-token",
+                name
+            ))),
+        }
+    }
+}
+
+/// A message posted through the Web API.
+#[derive(Debug, PartialEq)]
+pub struct PostedMessage {
+    pub channel: String,
+    pub ts: String,
+}
+
+#[derive(Serialize)]
+struct PostMessageRequest<'a> {
+    channel: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread_ts: Option<&'a str>,
+    #[serde(flatten)]
+    message: &'a SlackMessage,
+}
+
+#[derive(Deserialize)]
+struct PostMessageResponse {
+    ok: bool,
+    error: Option<String>,
+    channel: Option<String>,
+    ts: Option<String>,
+}
+
+pub fn send_slack_messages(
+    messages: &[SlackMessage],
+    destination: &Destination,
+    client: &HttpClient,
+) -> Result<()> {
+    let mut parent_ts = None;
+    for message in messages {
+        let thread_ts = if message.in_thread {
+            let ts = parent_ts.as_deref().ok_or_else(|| {
+                Error::Config(
+                    "Thread replies need a parent message posted with --bot-token".to_string(),
+                )
+            })?;
+            Some(ts)
+        } else {
+            None
+        };
+        let posted = send_slack_message(message, thread_ts, destination, client)?;
+        if parent_ts.is_none() {
+            parent_ts = posted.map(|posted| posted.ts);
+        }
+    }
+    println!("Results sent to Slack");
+    Ok(())
+}
+
+/// Posts one message, returning its channel and timestamp when the transport
+/// reports them.
+fn send_slack_message(
+    message: &SlackMessage,
+    thread_ts: Option<&str>,
+    destination: &Destination,
+    client: &HttpClient,
+) -> Result<Option<PostedMessage>> {
+    match destination {
+        Destination::Webhook(_) if thread_ts.is_some() => Err(Error::Config(
+            "Incoming webhooks cannot post thread replies".to_string(),
+        )),
+        Destination::Webhook(url) => {
+            client.post_json(url.as_str(), message, "Slack")?;
+            Ok(None)
+        }
+        Destination::WebApi {
+            api_url,
+            token,
+            channel,
+        } => {
+            let url = format!(
+                "{}/chat.postMessage",
+                api_url.as_str().trim_end_matches('/')
+            );
+            let request = PostMessageRequest {
+                channel,
+                thread_ts,
+                message,
+            };
+            let response: PostMessageResponse = client
+                .post_json_with_token(&url, token, &request, "Slack")?
+                .json()
+                .map_err(|err| Error::request_failed("Invalid response from Slack", err))?;
+            if !response.ok {
+                return Err(Error::delivery(format!(
+                    "Slack API error: {}",
+                    response.error.as_deref().unwrap_or("unknown error")
+                )));
+            }
+            match (response.channel, response.ts) {
+                (Some(channel), Some(ts)) => Ok(Some(PostedMessage { channel, ts })),
+                _ => Err(Error::delivery(
+                    "Slack API response is missing the message channel or ts",
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{failed_test, message_args, quarantined_test, test_run};
+    use crate::flaky::Rerun;
+    use crate::history::FailureStatus;
+    use crate::http::tests::test_client;
+    use junit_parser::{TestCase, TestFailure, TestStatus};
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    #[test]
+    fn test_append_case_info() {
+        let failed = failed_test("test_method");
+
+        let mut message = String::new();
+        append_case_info(&mut message, &failed, 2);
+        assert!(message.contains(&failed.case.name));
+        assert!(message.contains("junit.xml"));
+        assert!(message.contains("`AssertionError`: expected &lt;1&gt; but was &lt;2&gt;"));
+        assert!(message.contains("at test_method"));
+        assert!(!message.contains("at run"));
+    }
+
+    #[test]
+    fn test_describe_failure_without_details() {
+        let mut failed = failed_test("test_method");
+        failed.case.status = TestStatus::Failure(TestFailure::default());
+        assert_eq!(describe_failure(&failed, 5), "Failure");
+
+        let failed = failed_test("test_method");
+        assert!(!describe_failure(&failed, 0).contains("```"));
+    }
+
+    #[test]
+    fn test_format_slack_blocks() {
+        let summary = Summary {
+            total: 3,
+            passed: 2,
+            failed: 1,
+            duration: 65.0,
+            ..Default::default()
+        };
+        let blocks = format_slack_blocks(
+            &[failed_test("test_method")],
+            &summary,
+            &Groups::default(),
+            &message_args(20),
+        );
+        let json = serde_json::to_value(&blocks).unwrap();
+
+        assert_eq!(
+            json[0],
+            json!({"type": "header", "text": {"type": "plain_text", "text": "Nightly"}})
+        );
+        assert_eq!(
+            json[1]["fields"][6],
+            json!({"type": "mrkdwn", "text": "*Duration:* 1m 05s"})
+        );
+        assert_eq!(json[2], json!({"type": "divider"}));
+        let failure = json[3]["text"]["text"].as_str().unwrap();
+        assert!(failure.starts_with("*test_method*\n`AssertionError`"));
+        assert!(failure.ends_with("at test_method```"));
+    }
+
+    #[test]
+    fn test_format_slack_message_when_all_passed() {
+        let summary = Summary {
+            total: 3,
+            passed: 2,
+            skipped: 1,
+            duration: 1.0,
+            ..Default::default()
+        };
+        let message = build_slack_message(&[], &summary, &Groups::default(), &message_args(20));
+
+        assert!(message.text.starts_with(
+            "*Nightly*\n3 tests: 2 passed, 0 failed, 0 errored, 1 skipped (100.0% pass rate)"
+        ));
+        assert!(message.text.contains(ALL_PASSED));
+        let json = serde_json::to_value(&message.blocks).unwrap();
+        assert_eq!(json[1]["fields"][5]["text"], "*Pass rate:* 100.0%");
+        assert_eq!(json[2]["text"]["text"], ALL_PASSED);
+    }
+
+    #[test]
+    fn test_build_slack_messages_truncates_overflow() {
+        let messages = build_slack_messages(&test_run(30), &message_args(5));
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].text.ends_with("…and 25 more"));
+        let json = serde_json::to_value(&messages[0].blocks).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 3 + 5 * 2 + 1);
+    }
+
+    #[test]
+    fn test_build_slack_messages_keeps_overflow_notice_when_truncating() {
+        let mut run = test_run(2);
+        for failed in &mut run.failed_tests {
+            failed.case.name = "x".repeat(MAX_TEXT_LENGTH);
+        }
+
+        let messages = build_slack_messages(&run, &message_args(1));
+        assert!(messages[0].text.ends_with("…and 1 more"));
+        assert!(messages[0].text.chars().count() <= MAX_TEXT_LENGTH);
+    }
+
+    #[test]
+    fn test_build_slack_messages_follows_up_within_block_limit() {
+        let args = MessageArgs {
+            overflow: Overflow::FollowUp,
+            ..message_args(1000)
+        };
+
+        let messages = build_slack_messages(&test_run(100), &args);
+        assert_eq!(messages.len(), 6);
+        assert!(messages
+            .iter()
+            .all(|message| message.blocks.len() <= MAX_BLOCKS));
+        assert!(messages[0]
+            .text
+            .ends_with("…and 83 more in the following messages"));
+        assert!(messages[5].text.starts_with("*Nightly (continued 5/5)*"));
+        let listed: usize = messages
+            .iter()
+            .map(|message| message.text.matches("- *test_").count())
+            .sum();
+        assert_eq!(listed, 100);
+    }
+
+    #[test]
+    fn test_build_regression_messages_fit_block_limit_with_every_section() {
+        let mut run = test_run(30);
+        for failed in &mut run.failed_tests {
+            failed.known_issue = Some(KnownIssue {
+                label: "INFRA-123".to_string(),
+                url: None,
+            });
+        }
+        run.flaky_tests = vec![FlakyTest {
+            case: TestCase::default(),
+            attempts: 2,
+            first_failure: Rerun {
+                rerun_type: String::new(),
+                message: String::new(),
+                stack_trace: String::new(),
+            },
+        }];
+        run.quarantined_tests = vec![quarantined_test("test_muted")];
+        run.fixed_tests = vec!["test_fixed".to_string()];
+        run.mentions = vec![user_mention("U0123")];
+
+        let messages = build_regression_messages(&run, &Comparison::default(), &message_args(1000));
+        assert!(messages[0].blocks.len() <= MAX_BLOCKS);
+        assert!(messages[0].text.contains("\ncc <@U0123>\n"));
+    }
+
+    #[test]
+    fn test_send_slack_message_success() {
+        let mut server = Server::new();
+        let mock_url = server.url();
+
+        let mock = server
+            .mock("POST", "/")
+            .match_body(Matcher::PartialJson(json!({"text": "test message"})))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let message = SlackMessage {
+            text: "test message".to_string(),
+            blocks: vec![],
+            in_thread: false,
+        };
+        let destination = Destination::Webhook(mock_url.parse().unwrap());
+        send_slack_message(&message, None, &destination, &test_client()).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_slack_message_api_error() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .with_status(400)
+            .with_body("invalid_payload")
+            .create();
+
+        let message = SlackMessage {
+            text: "test message".to_string(),
+            blocks: vec![],
+            in_thread: false,
+        };
+        let destination = Destination::Webhook(server.url().parse().unwrap());
+        let err = send_slack_message(&message, None, &destination, &test_client()).unwrap_err();
+        mock.assert();
+        assert!(matches!(err, Error::Delivery { .. }));
+        assert!(err.to_string().contains("invalid_payload"));
+    }
+
+    fn web_api(server: &Server) -> Destination {
+        Destination::WebApi {
+            api_url: format!("{}/api/", server.url()).parse().unwrap(),
+            token: "xoxb-test".to_string(),
+            channel: "C123".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_send_slack_message_web_api() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/api/chat.postMessage")
+            .match_header("authorization", "Bearer xoxb-test")
+            .match_body(Matcher::PartialJson(
+                json!({"channel": "C123", "text": "test message"}),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok": true, "channel": "C123", "ts": "1700000000.000100"}"#)
+            .create();
+
+        let message = SlackMessage {
+            text: "test message".to_string(),
+            blocks: vec![],
+            in_thread: false,
+        };
+        let posted = send_slack_message(&message, None, &web_api(&server), &test_client()).unwrap();
+        mock.assert();
+        assert_eq!(
+            posted,
+            Some(PostedMessage {
+                channel: "C123".to_string(),
+                ts: "1700000000.000100".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_send_slack_message_web_api_error() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/api/chat.postMessage")
+            .with_status(200)
+            .with_body(r#"{"ok": false, "error": "channel_not_found"}"#)
+            .create();
+
+        let message = SlackMessage {
+            text: "test message".to_string(),
+            blocks: vec![],
+            in_thread: false,
+        };
+        let err =
+            send_slack_message(&message, None, &web_api(&server), &test_client()).unwrap_err();
+        mock.assert();
+        assert_eq!(err.to_string(), "Slack API error: channel_not_found");
+    }
+
+    #[test]
+    fn test_build_threaded_messages() {
+        let args = MessageArgs {
+            threaded: true,
+            ..message_args(3)
+        };
+
+        let messages = build_slack_messages(&test_run(30), &args);
+        assert_eq!(messages.len(), 3);
+        assert!(!messages[0].in_thread);
+        assert!(!messages[0].text.contains("```"));
+        assert!(messages[0].text.contains("• test_2\n…and 27 more\n"));
+        assert!(messages[1..].iter().all(|message| message.in_thread));
+        assert!(messages[2].text.starts_with("*Failure details (2/2)*"));
+        assert!(messages[2].text.contains("at test_2"));
+    }
+
+    #[test]
+    fn test_send_slack_messages_replies_in_thread() {
+        let mut server = Server::new();
+        let parent = server
+            .mock("POST", "/api/chat.postMessage")
+            .match_body(Matcher::PartialJson(json!({"text": "parent"})))
+            .with_body(r#"{"ok": true, "channel": "C123", "ts": "1.000100"}"#)
+            .create();
+        let reply = server
+            .mock("POST", "/api/chat.postMessage")
+            .match_body(Matcher::PartialJson(
+                json!({"text": "reply", "thread_ts": "1.000100"}),
+            ))
+            .with_body(r#"{"ok": true, "channel": "C123", "ts": "1.000200"}"#)
+            .create();
+
+        let messages = [
+            SlackMessage {
+                text: "parent".to_string(),
+                blocks: vec![],
+                in_thread: false,
+            },
+            SlackMessage {
+                text: "reply".to_string(),
+                blocks: vec![],
+                in_thread: true,
+            },
+        ];
+        send_slack_messages(&messages, &web_api(&server), &test_client()).unwrap();
+        parent.assert();
+        reply.assert();
+    }
+
+    #[test]
+    fn test_build_slack_messages_lists_flaky_tests() {
+        let mut run = test_run(0);
+        let flaky_test = |message: &str| FlakyTest {
+            case: TestCase {
+                name: "test_retry".to_string(),
+                ..Default::default()
+            },
+            attempts: 2,
+            first_failure: Rerun {
+                rerun_type: "TimeoutException".to_string(),
+                message: message.to_string(),
+                stack_trace: String::new(),
+            },
+        };
+        run.flaky_tests = vec![flaky_test("timed out\nafter 5s"), flaky_test("")];
+
+        let messages = build_slack_messages(&run, &message_args(20));
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].text.ends_with(
+            "*Flaky tests (2)*\n\
+             • *test_retry* passed on attempt 2, first failure: `TimeoutException`: timed out\n\
+             • *test_retry* passed on attempt 2, first failure: `TimeoutException`\n"
+        ));
+        let json = serde_json::to_value(&messages[0].blocks).unwrap();
+        let last = json.as_array().unwrap().last().unwrap();
+        assert!(last["text"]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("*Flaky tests (2)*"));
+    }
+
+    #[test]
+    fn test_build_slack_messages_marks_history() {
+        let mut run = test_run(2);
+        run.failed_tests[0].history = Some(FailureStatus::New);
+        run.failed_tests[1].history = Some(FailureStatus::StillFailing { streak: 3 });
+        run.fixed_tests = vec!["test_fixed".to_string()];
+
+        let messages = build_slack_messages(&run, &message_args(20));
+        let text = &messages[0].text;
+        assert!(text.contains("- *test_0* `NEW` (`junit.xml`)"));
+        assert!(text.contains("- *test_1* `STILL FAILING (3 runs)` ("));
+        assert!(text.ends_with("*Fixed since last run (1)*\n• test_fixed\n"));
+
+        let json = serde_json::to_value(&messages[0].blocks).unwrap();
+        assert!(json[3]["text"]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("*test_0* `NEW`\n"));
+    }
+
+    #[test]
+    fn test_build_slack_messages_appends_mentions() {
+        let mut run = test_run(1);
+        run.mentions = vec![user_mention("U0123"), user_group_mention("S0456")];
+
+        let messages = build_slack_messages(&run, &message_args(20));
+        assert!(messages[0].text.ends_with("\ncc <@U0123> <!subteam^S0456>"));
+        let json = serde_json::to_value(messages[0].blocks.last().unwrap()).unwrap();
+        assert_eq!(json["type"], "context");
+        assert_eq!(json["elements"][0]["text"], "cc <@U0123> <!subteam^S0456>");
+    }
+
+    #[test]
+    fn test_build_recovery_message() {
+        let mut run = test_run(0);
+        run.fixed_tests = vec!["test_a".to_string(), "test_b".to_string()];
+
+        let message = build_recovery_message(&run, &message_args(20));
+        assert_eq!(
+            message.text,
+            format!(
+                "*Nightly*\n{}\n\n{}\n\n*Fixed since last run (2)*\n• test_a\n• test_b\n",
+                run.summary, BACK_TO_GREEN
+            )
+        );
+        let json = serde_json::to_value(&message.blocks).unwrap();
+        assert_eq!(json[2]["text"]["text"], BACK_TO_GREEN);
+        assert_eq!(json.as_array().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_build_regression_messages() {
+        let run = test_run(1);
+        let comparison = Comparison {
+            newly_failing: vec!["test_0".to_string()],
+            removed: vec!["test_old".to_string()],
+            ..Default::default()
+        };
+
+        let messages = build_regression_messages(&run, &comparison, &message_args(20));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].text.matches("- *test_0*").count(), 1);
+        assert!(messages[0].text.ends_with(
+            "*Compared with the baseline*\n1 newly failing, 0 newly passing, 0 newly skipped, 0 added, 1 removed\n"
+        ));
+    }
+
+    #[test]
+    fn test_build_slack_messages_mutes_quarantined_tests() {
+        let mut run = test_run(0);
+        run.quarantined_tests = vec![quarantined_test("test_muted")];
+        run.expired_quarantine = vec![QuarantineEntry {
+            classname: glob::Pattern::new("com.example.Old").unwrap(),
+            name: glob::Pattern::new("*").unwrap(),
+            ticket: None,
+            ticket_url: None,
+            expires: Some(toml::value::Date {
+                year: 2026,
+                month: 1,
+                day: 31,
+            }),
+        }];
+
+        let messages = build_slack_messages(&run, &message_args(20));
+        assert!(messages[0].text.ends_with(
+            "*Quarantined failures (1)*\n\
+             • test_muted (<https://jira.example.com/browse/PAY-12|PAY-12>)\n\
+             :warning: Quarantine of `com.example.Old::*` expired on 2026-01-31\n"
+        ));
+    }
+
+    #[test]
+    fn test_build_slack_messages_groups_known_issues() {
+        let mut run = test_run(3);
+        for index in [0, 2] {
+            run.failed_tests[index].known_issue = Some(KnownIssue {
+                label: "INFRA-123".to_string(),
+                url: Some("https://jira.example.com/browse/INFRA-123".to_string()),
+            });
+        }
+
+        let messages = build_slack_messages(&run, &message_args(20));
+        let text = &messages[0].text;
+        assert_eq!(
+            text.matches("Known issue: <https://jira.example.com/browse/INFRA-123|INFRA-123>\n")
+                .count(),
+            2
+        );
+        assert!(text.ends_with(
+            "*Failures by known issue*\n\
+             • No known issue (1): test_1\n\
+             • <https://jira.example.com/browse/INFRA-123|INFRA-123> (2): test_0, test_2\n"
+        ));
+    }
+
+    #[test]
+    fn test_build_slack_messages_clusters_failures() {
+        let mut run = test_run(8);
+        let mut other = failed_test("test_other");
+        other.case.status = TestStatus::Failure(TestFailure::default());
+        run.failed_tests[7] = other;
+        // The same trace in every other failure, whatever the test's name.
+        for failed in &mut run.failed_tests[..7] {
+            failed.case.status = failed_test("test_method").case.status;
+        }
+        let args = MessageArgs {
+            layout: Layout::Clusters,
+            ..message_args(20)
+        };
+
+        let messages = build_slack_messages(&run, &args);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].text.ends_with(
+            "- *7 tests* with the same error\n\
+             `AssertionError`: expected &lt;1&gt; but was &lt;2&gt;\n\
+             ```AssertionError\n\
+             \tat test_method```\n\
+             test_0, test_1, test_2, test_3, test_4, …and 2 more\n\
+             - *1 test*\nFailure\ntest_other\n"
+        ));
+        let json = serde_json::to_value(&messages[0].blocks).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 3 + 2 * 2);
+    }
+
+    #[test]
+    fn test_build_slack_messages_groups_failures() {
+        let in_class = |classname: &str, name: &str| {
+            let mut failed = failed_test(name);
+            failed.case.classname = Some(classname.to_string());
+            failed
+        };
+        let mut run = test_run(3);
+        run.failed_tests = vec![
+            in_class("com.example.db.UserTest", "a"),
+            in_class("com.example.api.AuthTest", "b"),
+            in_class("com.example.db.OrderTest", "c"),
+        ];
+        let args = MessageArgs {
+            group_by: GroupBy::Package,
+            stack_trace_lines: 0,
+            ..message_args(20)
+        };
+
+        let messages = build_slack_messages(&run, &args);
+        let listed: Vec<&str> = messages[0]
+            .text
+            .lines()
+            .filter(|line| line.starts_with('*') || line.starts_with("- "))
+            .skip(1)
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                "*com.example.db* (2 failed)",
+                "- *a* (`junit.xml`)",
+                "- *c* (`junit.xml`)",
+                "*com.example.api* (1 failed)",
+                "- *b* (`junit.xml`)",
+            ]
+        );
+        let json = serde_json::to_value(&messages[0].blocks).unwrap();
+        assert!(json[3]["text"]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("*com.example.db* (2 failed)\n\n*a*"));
+        assert!(json[5]["text"]["text"].as_str().unwrap().starts_with("*c*"));
+    }
+}
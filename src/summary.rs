@@ -0,0 +1,181 @@
+use crate::input::ReportFile;
+use junit_parser::{TestStatus, TestSuite};
+use serde::Serialize;
+use std::fmt;
+
+/// Test counts and duration across all loaded reports.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub errored: usize,
+    pub skipped: usize,
+    /// Passed tests that failed at least once before passing on a rerun.
+    pub flaky: usize,
+    /// Sum of the `time` attribute of the top-level suites, in seconds.
+    pub duration: f64,
+}
+
+impl Summary {
+    pub fn from_reports(reports: &[ReportFile]) -> Self {
+        let mut summary = Summary::default();
+        for report in reports {
+            for suite in &report.suites {
+                summary.duration += suite.time;
+            }
+            summary.count_cases(report, &report.suites);
+        }
+        summary
+    }
+
+    /// Percentage of executed (non-skipped) tests that passed, if any were executed.
+    pub fn pass_rate(&self) -> Option<f64> {
+        let executed = self.total - self.skipped;
+        (executed > 0).then(|| self.passed as f64 * 100.0 / executed as f64)
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.failed == 0 && self.errored == 0
+    }
+
+    fn count_cases(&mut self, report: &ReportFile, suites: &[TestSuite]) {
+        for suite in suites {
+            self.count_cases(report, &suite.suites);
+            for case in &suite.cases {
+                self.total += 1;
+                match case.status {
+                    TestStatus::Success => {
+                        self.passed += 1;
+                        if !report.reruns(case).is_empty() {
+                            self.flaky += 1;
+                        }
+                    }
+                    TestStatus::Failure(_) => self.failed += 1,
+                    TestStatus::Error(_) => self.errored += 1,
+                    TestStatus::Skipped(_) => self.skipped += 1,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} tests: {} passed", self.total, self.passed)?;
+        if self.flaky > 0 {
+            write!(f, " ({} flaky)", self.flaky)?;
+        }
+        write!(
+            f,
+            ", {} failed, {} errored, {} skipped",
+            self.failed, self.errored, self.skipped
+        )?;
+        if let Some(pass_rate) = self.pass_rate() {
+            write!(f, " ({} pass rate)", format_pass_rate(pass_rate))?;
+        }
+        write!(f, " in {}", format_duration(self.duration))
+    }
+}
+
+/// Formats a pass rate, never rounding a partial pass up to `100%`.
+pub fn format_pass_rate(pass_rate: f64) -> String {
+    let tenths = (pass_rate * 10.0).floor() / 10.0;
+    format!("{:.1}%", tenths)
+}
+
+/// Formats a duration in seconds as e.g. `4.2s`, `3m 05s` or `1h 02m`.
+pub fn format_duration(seconds: f64) -> String {
+    let whole = seconds.round() as u64;
+    if seconds < 60.0 {
+        format!("{:.1}s", seconds)
+    } else if whole < 3600 {
+        format!("{}m {:02}s", whole / 60, whole % 60)
+    } else {
+        format!("{}h {:02}m", whole / 3600, whole % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_summary_from_reports() {
+        let xml = r#"<testsuites>
+            <testsuite name="a" time="1.5">
+                <testcase name="ok"/>
+                <testcase name="flaky"><flakyFailure message="once"/></testcase>
+                <testcase name="failed"><failure/></testcase>
+                <testsuite name="nested" time="0.5">
+                    <testcase name="errored"><error/></testcase>
+                    <testcase name="skipped"><skipped/></testcase>
+                </testsuite>
+            </testsuite>
+            <testsuite name="b" time="2"><testcase name="ok"/></testsuite>
+        </testsuites>"#;
+        let report = ReportFile {
+            path: PathBuf::from("junit.xml"),
+            suites: junit_parser::from_reader(xml.as_bytes()).unwrap().suites,
+            reruns: crate::flaky::parse_reruns(xml).unwrap(),
+        };
+
+        let summary = Summary::from_reports(&[report]);
+        assert_eq!(
+            summary,
+            Summary {
+                total: 6,
+                passed: 3,
+                failed: 1,
+                errored: 1,
+                skipped: 1,
+                flaky: 1,
+                duration: 3.5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_pass_rate_ignores_skipped_tests() {
+        let summary = Summary {
+            total: 4,
+            passed: 2,
+            failed: 1,
+            skipped: 1,
+            duration: 4.0,
+            ..Default::default()
+        };
+        assert_eq!(format_pass_rate(summary.pass_rate().unwrap()), "66.6%");
+        assert_eq!(
+            summary.to_string(),
+            "4 tests: 2 passed, 1 failed, 0 errored, 1 skipped (66.6% pass rate) in 4.0s"
+        );
+
+        let all_skipped = Summary {
+            total: 1,
+            skipped: 1,
+            ..Default::default()
+        };
+        assert_eq!(all_skipped.pass_rate(), None);
+        assert!(all_skipped.all_passed());
+        assert_eq!(format_pass_rate(99.99), "99.9%");
+
+        let flaky = Summary {
+            total: 2,
+            passed: 2,
+            flaky: 1,
+            ..Default::default()
+        };
+        assert!(flaky
+            .to_string()
+            .starts_with("2 tests: 2 passed (1 flaky), 0 failed"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(4.25), "4.2s");
+        assert_eq!(format_duration(185.0), "3m 05s");
+        assert_eq!(format_duration(3720.0), "1h 02m");
+    }
+}
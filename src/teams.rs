@@ -0,0 +1,504 @@
+use crate::cli::MessageArgs;
+use crate::diff::Comparison;
+use crate::error::{Error, Result};
+use crate::http::HttpClient;
+use crate::known_issues::KnownIssue;
+use crate::notifier::{
+    self, escape_markdown, ListItem, ListSection, Notifier, ALL_PASSED, BACK_TO_GREEN,
+};
+use crate::summary::{format_duration, format_pass_rate, Summary};
+use crate::{FailedTest, TestRun};
+use reqwest::Url;
+use serde::Serialize;
+
+const SCHEMA: &str = "http://adaptivecards.io/schemas/adaptive-card.json";
+/// Newest Adaptive Card version that Teams renders on every client.
+const CARD_VERSION: &str = "1.5";
+/// Teams rejects messages above roughly 28 KB; this leaves room for the
+/// message around the card and the "…and N more" line.
+const MAX_CARD_BYTES: usize = 27_000;
+const MAX_TEXT_LENGTH: usize = 2_000;
+
+/// Payload accepted by Teams incoming webhooks and by the "post to a channel
+/// when a webhook request is received" Workflows template.
+#[derive(Serialize)]
+pub struct TeamsMessage {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Attachment {
+    content_type: &'static str,
+    content: AdaptiveCard,
+}
+
+#[derive(Serialize)]
+pub struct AdaptiveCard {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    version: &'static str,
+    body: Vec<Element>,
+    msteams: MsTeams,
+}
+
+/// Teams-specific card settings.
+#[derive(Serialize)]
+struct MsTeams {
+    width: &'static str,
+}
+
+/// An Adaptive Card element.
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub enum Element {
+    TextBlock(TextBlock),
+    FactSet { facts: Vec<Fact> },
+    Container(Container),
+    ActionSet { actions: Vec<Action> },
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextBlock {
+    text: String,
+    wrap: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weight: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    font_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    is_subtle: bool,
+}
+
+#[derive(Serialize)]
+pub struct Fact {
+    title: String,
+    value: String,
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Container {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    items: Vec<Element>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    separator: bool,
+    /// Hidden containers are shown by an `Action.ToggleVisibility`.
+    #[serde(skip_serializing_if = "is_true")]
+    is_visible: bool,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub enum Action {
+    #[serde(rename = "Action.ToggleVisibility", rename_all = "camelCase")]
+    ToggleVisibility {
+        title: String,
+        target_elements: Vec<String>,
+    },
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+fn text(text: impl Into<String>) -> Element {
+    Element::TextBlock(TextBlock {
+        text: notifier::truncate(&text.into(), MAX_TEXT_LENGTH),
+        wrap: true,
+        ..Default::default()
+    })
+}
+
+fn heading(text: impl Into<String>) -> Element {
+    Element::TextBlock(TextBlock {
+        text: text.into(),
+        wrap: true,
+        weight: Some("Bolder"),
+        ..Default::default()
+    })
+}
+
+/// Posts Adaptive Cards to a Teams channel.
+pub struct TeamsNotifier<'a> {
+    url: Url,
+    client: &'a HttpClient,
+}
+
+impl<'a> TeamsNotifier<'a> {
+    pub fn new(url: Url, client: &'a HttpClient) -> Self {
+        TeamsNotifier { url, client }
+    }
+
+    fn send(&self, card: AdaptiveCard) -> Result<()> {
+        let message = TeamsMessage {
+            kind: "message",
+            attachments: vec![Attachment {
+                content_type: "application/vnd.microsoft.card.adaptive",
+                content: card,
+            }],
+        };
+        let response = self
+            .client
+            .post_json(self.url.as_str(), &message, "Teams")?;
+        // Incoming webhooks answer `200 OK` even when the card was rejected,
+        // with the reason in the body. Workflows answer `202` with no body.
+        let body = response.text().unwrap_or_default();
+        if body.starts_with("Webhook message delivery failed") {
+            return Err(Error::delivery(format!("Teams API error: {}", body)));
+        }
+        println!("Results sent to Teams");
+        Ok(())
+    }
+}
+
+impl Notifier for TeamsNotifier<'_> {
+    fn notify(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(build_card(run, args))
+    }
+
+    fn notify_recovery(&self, run: &TestRun, args: &MessageArgs) -> Result<()> {
+        self.send(build_recovery_card(run, args))
+    }
+
+    fn notify_regressions(
+        &self,
+        run: &TestRun,
+        comparison: &Comparison,
+        args: &MessageArgs,
+    ) -> Result<()> {
+        self.send(build_regression_card(run, comparison, args))
+    }
+}
+
+/// Builds a card with the summary as a facts table and one entry per
+/// failure, up to `--max-failures` and Teams' size limit. Each entry shows
+/// the test and why it failed, and hides its stack trace behind a "Show
+/// details" toggle.
+///
+/// Flaky tests, quarantined failures and tests fixed since the previous run
+/// are listed at the end of the card.
+pub fn build_card(run: &TestRun, args: &MessageArgs) -> AdaptiveCard {
+    let mut body = header(&args.title, &run.summary);
+    if run.summary.all_passed() {
+        body.push(Element::TextBlock(TextBlock {
+            text: ALL_PASSED.to_string(),
+            wrap: true,
+            color: Some("Good"),
+            ..Default::default()
+        }));
+    }
+    let sections: Vec<Element> = notifier::list_sections(run).iter().map(section).collect();
+
+    let mut length: usize = body.iter().chain(&sections).map(byte_size).sum();
+    let mut listed = 0;
+    for (index, failed) in run.failed_tests.iter().take(args.max_failures).enumerate() {
+        let failure = failure_element(failed, index, args.stack_trace_lines);
+        length += byte_size(&failure);
+        if length > MAX_CARD_BYTES {
+            break;
+        }
+        body.push(failure);
+        listed += 1;
+    }
+    let remaining = run.failed_tests.len() - listed;
+    if remaining > 0 {
+        body.push(text(format!("…and {} more", remaining)));
+    }
+    body.extend(sections);
+    card(body)
+}
+
+/// Adds the counts of every kind of change since the baseline to the card
+/// of the newly failing tests, as a second facts table.
+pub fn build_regression_card(
+    run: &TestRun,
+    comparison: &Comparison,
+    args: &MessageArgs,
+) -> AdaptiveCard {
+    let mut card = build_card(run, args);
+    card.body.push(heading("Compared with the baseline"));
+    card.body.push(Element::FactSet {
+        facts: [
+            ("Newly failing", &comparison.newly_failing),
+            ("Newly passing", &comparison.newly_passing),
+            ("Newly skipped", &comparison.newly_skipped),
+            ("Added", &comparison.added),
+            ("Removed", &comparison.removed),
+        ]
+        .into_iter()
+        .map(|(title, names)| fact(title, names.len()))
+        .collect(),
+    });
+    card
+}
+
+/// A card in the "Good" color for a run that passes again, with the tests
+/// it fixed.
+pub fn build_recovery_card(run: &TestRun, args: &MessageArgs) -> AdaptiveCard {
+    let mut body = header(&args.title, &run.summary);
+    body.push(Element::TextBlock(TextBlock {
+        text: BACK_TO_GREEN.to_string(),
+        wrap: true,
+        color: Some("Good"),
+        ..Default::default()
+    }));
+    body.extend(notifier::recovery_sections(run).iter().map(section));
+    card(body)
+}
+
+fn card(body: Vec<Element>) -> AdaptiveCard {
+    AdaptiveCard {
+        schema: SCHEMA,
+        kind: "AdaptiveCard",
+        version: CARD_VERSION,
+        body,
+        msteams: MsTeams { width: "Full" },
+    }
+}
+
+/// The title followed by the summary as a facts table.
+fn header(title: &str, summary: &Summary) -> Vec<Element> {
+    let mut facts = vec![
+        fact("Total", summary.total),
+        fact("Passed", summary.passed),
+        fact("Failed", summary.failed),
+        fact("Errored", summary.errored),
+        fact("Skipped", summary.skipped),
+    ];
+    if summary.flaky > 0 {
+        facts.push(fact("Flaky", summary.flaky));
+    }
+    if let Some(pass_rate) = summary.pass_rate() {
+        facts.push(fact("Pass rate", format_pass_rate(pass_rate)));
+    }
+    facts.push(fact("Duration", format_duration(summary.duration)));
+    vec![
+        Element::TextBlock(TextBlock {
+            text: title.to_string(),
+            wrap: true,
+            size: Some("Large"),
+            weight: Some("Bolder"),
+            ..Default::default()
+        }),
+        Element::FactSet { facts },
+    ]
+}
+
+/// Size of the element in the serialized card.
+fn byte_size(element: &Element) -> usize {
+    serde_json::to_vec(element).map_or(0, |json| json.len())
+}
+
+fn fact(title: &str, value: impl ToString) -> Fact {
+    Fact {
+        title: title.to_string(),
+        value: value.to_string(),
+    }
+}
+
+/// A failed test with its reason, and its stack trace in a collapsed
+/// container.
+fn failure_element(failed: &FailedTest, index: usize, stack_trace_lines: usize) -> Element {
+    let name = match failed.history {
+        Some(status) => format!("{} ({})", escape_markdown(&failed.case.name), status),
+        None => escape_markdown(&failed.case.name),
+    };
+    let mut items = vec![
+        Element::TextBlock(TextBlock {
+            text: name,
+            wrap: true,
+            weight: Some("Bolder"),
+            color: Some("Attention"),
+            ..Default::default()
+        }),
+        Element::TextBlock(TextBlock {
+            text: failed.source.display().to_string(),
+            wrap: true,
+            is_subtle: true,
+            ..Default::default()
+        }),
+    ];
+    let reason = escape_markdown(&failed.reason());
+    if !reason.is_empty() {
+        items.push(text(reason));
+    }
+    if let Some(issue) = &failed.known_issue {
+        items.push(text(format!("Known issue: {}", format_known_issue(issue))));
+    }
+    let stack_trace = failed.stack_trace(stack_trace_lines);
+    if !stack_trace.is_empty() {
+        let id = format!("failure-{}", index);
+        items.push(Element::ActionSet {
+            actions: vec![Action::ToggleVisibility {
+                title: "Show details".to_string(),
+                target_elements: vec![id.clone()],
+            }],
+        });
+        items.push(Element::Container(Container {
+            id: Some(id),
+            items: vec![Element::TextBlock(TextBlock {
+                text: notifier::truncate(
+                    &escape_markdown(&stack_trace.join("\n")),
+                    MAX_TEXT_LENGTH,
+                ),
+                wrap: true,
+                font_type: Some("Monospace"),
+                ..Default::default()
+            })],
+            is_visible: false,
+            ..Default::default()
+        }));
+    }
+    Element::Container(Container {
+        items,
+        separator: true,
+        is_visible: true,
+        ..Default::default()
+    })
+}
+
+fn format_known_issue(issue: &KnownIssue) -> String {
+    match &issue.url {
+        Some(url) => format!("[{}]({})", escape_markdown(&issue.label), url),
+        None => escape_markdown(&issue.label),
+    }
+}
+
+/// The entry with its note linked to the item's URL, if it has one.
+fn format_item(item: &ListItem) -> String {
+    let name = escape_markdown(item.name);
+    match (&item.note, item.link) {
+        (Some(note), Some(url)) => format!("{} ([{}]({}))", name, escape_markdown(note), url),
+        (Some(note), None) => format!("{} ({})", name, escape_markdown(note)),
+        _ => name,
+    }
+}
+
+/// A heading with the number of entries, followed by one line per entry.
+fn section(list: &ListSection) -> Element {
+    let lines: Vec<String> = list
+        .items
+        .iter()
+        .map(|item| format!("- {}", format_item(item)))
+        .collect();
+    Element::Container(Container {
+        items: vec![heading(list.heading()), text(lines.join("\n"))],
+        separator: true,
+        is_visible: true,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{message_args, test_run};
+    use crate::history::FailureStatus;
+    use crate::http::tests::test_client;
+    use junit_parser::{TestFailure, TestStatus};
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    #[test]
+    fn test_build_card() {
+        let mut run = test_run(2);
+        run.failed_tests[0].history = Some(FailureStatus::New);
+        run.failed_tests[0].case.name = "test_[a]*b*".to_string();
+        let card = serde_json::to_value(build_card(&run, &message_args(1))).unwrap();
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(card["body"][0]["text"], "Nightly");
+        assert_eq!(card["body"][1]["type"], "FactSet");
+        assert_eq!(
+            card["body"][1]["facts"][2],
+            json!({"title": "Failed", "value": "2"})
+        );
+        assert_eq!(
+            card["body"][1]["facts"][5],
+            json!({"title": "Pass rate", "value": "33.3%"})
+        );
+
+        let failure = &card["body"][2]["items"];
+        assert_eq!(failure[0]["text"], r"test\_\[a\]\*b\* (NEW)");
+        assert_eq!(
+            failure[2]["text"],
+            "AssertionError: expected <1> but was <2>"
+        );
+        assert_eq!(
+            failure[3]["actions"][0],
+            json!({
+                "type": "Action.ToggleVisibility",
+                "title": "Show details",
+                "targetElements": ["failure-0"],
+            })
+        );
+        assert_eq!(failure[4]["id"], "failure-0");
+        assert_eq!(failure[4]["isVisible"], false);
+        assert_eq!(
+            failure[4]["items"][0]["text"],
+            "AssertionError\n\tat test\\_0"
+        );
+        assert_eq!(card["body"][3]["text"], "…and 1 more");
+    }
+
+    #[test]
+    fn test_build_card_stays_within_size_limit() {
+        let mut run = test_run(1);
+        let mut failed = run.failed_tests[0].clone();
+        failed.case.status = TestStatus::Failure(TestFailure {
+            message: "x".repeat(5_000),
+            text: "at Test.run\n".repeat(1_000),
+            failure_type: "AssertionError".to_string(),
+        });
+        run.failed_tests = vec![failed; 20];
+        let args = MessageArgs {
+            stack_trace_lines: 1_000,
+            ..message_args(20)
+        };
+
+        let card = build_card(&run, &args);
+        assert!(serde_json::to_vec(&card).unwrap().len() <= MAX_CARD_BYTES + 100);
+        let json = serde_json::to_value(&card).unwrap();
+        let last = json["body"].as_array().unwrap().last().unwrap().clone();
+        assert!(last["text"].as_str().unwrap().starts_with("…and "));
+    }
+
+    #[test]
+    fn test_teams_notifier_posts_adaptive_card() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/webhook")
+            .match_body(Matcher::PartialJson(json!({
+                "type": "message",
+                "attachments": [{
+                    "contentType": "application/vnd.microsoft.card.adaptive",
+                    "content": {"type": "AdaptiveCard", "version": "1.5"},
+                }],
+            })))
+            .with_body("1")
+            .create();
+        let client = test_client();
+        let url = format!("{}/webhook", server.url()).parse().unwrap();
+        let notifier = TeamsNotifier::new(url, &client);
+
+        notifier.notify(&test_run(2), &message_args(20)).unwrap();
+        mock.assert();
+        mock.remove();
+
+        server
+            .mock("POST", "/webhook")
+            .with_body("Webhook message delivery failed with error: Microsoft Teams endpoint returned HTTP error 413")
+            .create();
+        assert!(notifier.notify(&test_run(2), &message_args(20)).is_err());
+    }
+}